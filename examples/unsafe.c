@@ -14,5 +14,7 @@ int main() {
         printf(input, 1); // adding an arg here evades even -Wpedantic...
         printf("%s is %s", input); // gcc catches this though
         printf("%s", input, 42);
+        int printed;
+        printf("%s%n", input, &printed); // writes to memory through the format string
     }
 }
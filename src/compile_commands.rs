@@ -0,0 +1,73 @@
+use miette::{Context, IntoDiagnostic};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// An entry in a `compile_commands.json` compilation database.
+#[derive(Debug, Deserialize)]
+struct CompileCommand {
+    /// The working directory of the compilation.
+    directory: PathBuf,
+    /// The source file, which may be relative to `directory`.
+    file: PathBuf,
+}
+
+/// Reads a `compile_commands.json` and returns the C source files it compiles,
+/// in the order they first appear.
+pub fn source_files(path: &Path) -> miette::Result<Vec<PathBuf>> {
+    let json = fs::read_to_string(path)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed reading compile commands at {}", path.display()))?;
+
+    let commands: Vec<CompileCommand> = serde_json::from_str(&json)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed parsing compile commands at {}", path.display()))?;
+
+    let mut files: Vec<PathBuf> = Vec::with_capacity(commands.len());
+    for command in commands {
+        let file = command.directory.join(command.file);
+        if file.extension().is_some_and(|ext| ext == "c") && !files.contains(&file) {
+            files.push(file);
+        }
+    }
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::scratch_dir;
+
+    #[test]
+    fn lists_each_c_file_once() {
+        let dir = scratch_dir("compile-commands");
+        let path = dir.join("compile_commands.json");
+        let json = serde_json::json!([
+            { "directory": dir.join("build"), "file": "../src/main.c", "command": "cc -c ../src/main.c" },
+            { "directory": dir, "file": dir.join("src/util.c"), "arguments": ["cc", "-c", "src/util.c"] },
+            { "directory": dir, "file": "src/util.h", "command": "cc -c src/util.h" },
+            { "directory": dir.join("build"), "file": "../src/main.c", "command": "cc -O2 -c ../src/main.c" },
+        ]);
+        fs::write(&path, json.to_string()).unwrap();
+
+        assert_eq!(
+            source_files(&path).unwrap(),
+            [dir.join("build/../src/main.c"), dir.join("src/util.c")]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reports_invalid_json() {
+        let dir = scratch_dir("compile-commands-invalid");
+        let path = dir.join("compile_commands.json");
+        fs::write(&path, r#"[{ "file": "main.c" }]"#).unwrap();
+
+        let error = source_files(&path).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("failed parsing compile commands"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
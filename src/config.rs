@@ -0,0 +1,90 @@
+use crate::ir::CType;
+use crate::lint::Lint;
+use crate::rules::RuleConfig;
+use std::collections::HashMap;
+
+/// Settings that change how C source code is validated.
+#[derive(Debug)]
+pub struct Config {
+    /// Typedef'd names that casts can refer to e.g. `uint32_t`, mapped to
+    /// the C type they format as.
+    pub typedefs: HashMap<String, CType>,
+
+    /// Functions mapped to the C type they return, so that arguments like
+    /// `get_count()` can be type checked.
+    pub fn_returns: HashMap<String, CType>,
+
+    /// Report casts that are redundant, and don't add casts to arguments
+    /// that obviously have the type the specifier expects, like literals.
+    pub warn_redundant_casts: bool,
+
+    /// Report `sprintf` and `snprintf` buffers and `asprintf` out-pointers that
+    /// obviously aren't pointers, and sizes that obviously aren't integers.
+    pub check_buffers: bool,
+
+    /// Accept C++ raw string literals, e.g. `R"(%d)"`, as format strings.
+    pub cpp: bool,
+
+    /// Recognize Microsoft's `%C` and `%S`, which format wide characters
+    /// and strings.
+    pub ms_compat: bool,
+
+    /// C standard to validate against, which decides whether specifiers
+    /// like C23's `%b` are recognized.
+    pub standard: Standard,
+
+    /// Report specifiers that are well-defined but likely mistakes, and
+    /// casts that exactly match their specifier.
+    pub pedantic: bool,
+
+    /// How deeply parentheses can be nested in an argument before it's
+    /// reported instead of parsed.
+    pub max_nesting: usize,
+
+    /// Levels that rules are reported at, overriding their default severity.
+    pub rules: RuleConfig,
+
+    /// Extra checks to run on each call site after the built-in ones.
+    ///
+    /// Use [`Config::add_lint`] to add one, which also registers its rules.
+    pub lints: Vec<Box<dyn Lint>>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            typedefs: HashMap::default(),
+            fn_returns: HashMap::default(),
+            warn_redundant_casts: false,
+            check_buffers: false,
+            cpp: false,
+            ms_compat: false,
+            standard: Standard::default(),
+            pedantic: false,
+            max_nesting: 256,
+            rules: RuleConfig::default(),
+            lints: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Adds a lint to run on each call site, registering its rules in
+    /// [`Config::rules`] so that their level can be set.
+    pub fn add_lint(&mut self, lint: impl Lint + 'static) {
+        for rule in lint.rules() {
+            self.rules.register(rule);
+        }
+        self.lints.push(Box::new(lint));
+    }
+}
+
+/// Versions of the C standard, in order, that change which specifiers exist.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Standard {
+    /// C17, and the standards before it.
+    #[default]
+    C17,
+    /// C23, which adds `%b` and `%B` for binary integers.
+    C23,
+}
@@ -0,0 +1,205 @@
+use crate::config::Config;
+use crate::error::Error;
+use crate::function::Function;
+use crate::ir;
+use crate::lex::{ArgToken, SourceToken};
+use crate::location::{LineIndex, Location};
+use crate::macros::Macros;
+use crate::parse::Args;
+use displaydoc::Display;
+use logos::Logos;
+
+/// A call to `printf` or family that can't be fully validated.
+#[derive(Debug)]
+pub struct Unvalidated {
+    /// The function called, e.g. `printf`.
+    pub function: Function,
+    /// Location of the function name.
+    pub location: Location,
+    /// Why the call can't be fully validated.
+    pub reason: Reason,
+}
+
+/// Reasons that a call can't be fully validated.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Display)]
+pub enum Reason {
+    /// format string isn't a string literal
+    NonliteralFormat,
+    /// arguments come from `__VA_ARGS__`
+    VaArgs,
+}
+
+/// Returns the calls to `printf` and family in C source code that can't be
+/// fully validated, e.g. because the format string is a variable.
+///
+/// Unlike validation, this includes calls in `#define` bodies, which is
+/// where arguments from `__VA_ARGS__` come up, like in
+/// `#define LOG(...) printf("%d\n", __VA_ARGS__)`.
+pub fn unvalidated(source: &str, config: &Config) -> Vec<Unvalidated> {
+    let line_index = LineIndex::new(source);
+    let macros = Macros::collect(source);
+    let mut unvalidated = Vec::new();
+
+    scan(source, 0, config, &macros, &line_index, &mut unvalidated);
+
+    let mut lex = SourceToken::lexer(source);
+    while let Some(token) = lex.next() {
+        if token != SourceToken::Define {
+            continue;
+        }
+        // the body after `#define`, so it isn't lexed as a `#define` again
+        let span = lex.span();
+        let body_start = span.start + lex.slice().find("define").map_or(0, |i| i + "define".len());
+        let body = &source[body_start..span.end];
+        scan(
+            body,
+            body_start,
+            config,
+            &macros,
+            &line_index,
+            &mut unvalidated,
+        );
+    }
+
+    unvalidated.sort_by_key(|call| call.location.offset);
+    unvalidated
+}
+
+/// Pushes the calls in `source` that can't be fully validated, where
+/// `source` starts at `offset` in the whole source code.
+fn scan<'src>(
+    source: &'src str,
+    offset: usize,
+    config: &Config,
+    macros: &Macros<'src>,
+    line_index: &LineIndex<'_>,
+    unvalidated: &mut Vec<Unvalidated>,
+) {
+    let mut lex = SourceToken::lexer(source);
+
+    while let Some((function, name_span)) = ir::next_call(&mut lex) {
+        let pre_args = function.pre_args().len();
+        let location = line_index.location(offset + name_span.start);
+
+        let mut args = Args::new(&mut lex, config);
+        if args.by_ref().take(pre_args).count() < pre_args {
+            continue;
+        }
+
+        let reason = match args.next_format_string(macros) {
+            Ok(_) => args
+                .by_ref()
+                .any(|arg| arg.first_token == Some(ArgToken::Identifier("__VA_ARGS__")))
+                .then_some(Reason::VaArgs),
+            Err(Error::NonliteralFormat { .. }) => Some(Reason::NonliteralFormat),
+            Err(_) => None,
+        };
+        // move the lexer to the end of the call
+        args.short_circuit();
+
+        if let Some(reason) = reason {
+            unvalidated.push(Unvalidated {
+                function,
+                location,
+                reason,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the line and reason of each unvalidated call.
+    fn reasons(source: &str) -> Vec<(usize, Reason)> {
+        unvalidated(source, &Config::default())
+            .into_iter()
+            .map(|call| (call.location.line, call.reason))
+            .collect()
+    }
+
+    #[test]
+    fn nonliteral_format() {
+        assert_eq!(
+            reasons("printf(fmt, x);\nprintf(\"%d\", x);\nsprintf(buf, msg);"),
+            [(1, Reason::NonliteralFormat), (3, Reason::NonliteralFormat)]
+        );
+    }
+
+    #[test]
+    fn calls_with_pre_args() {
+        let source = "sprintf(buf, msg);\n\
+                      snprintf(buf, sizeof buf, \"%d\", x);\n\
+                      snprintf(buf, sizeof buf, \"%d\", __VA_ARGS__);\n";
+        let calls = unvalidated(source, &Config::default());
+        let calls: Vec<(&str, usize, String)> = calls
+            .iter()
+            .map(|call| {
+                (
+                    call.function.name(),
+                    call.location.line,
+                    call.reason.to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            calls,
+            [
+                (
+                    "sprintf",
+                    1,
+                    "format string isn't a string literal".to_string()
+                ),
+                (
+                    "snprintf",
+                    3,
+                    "arguments come from `__VA_ARGS__`".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn va_args_in_define() {
+        assert_eq!(
+            reasons("#define LOG(...) printf(\"%d\\n\", __VA_ARGS__)\nLOG(1);"),
+            [(1, Reason::VaArgs)]
+        );
+    }
+
+    #[test]
+    fn va_args_in_multiline_define() {
+        let source = "printf(fmt);\n\
+                      #define LOG(...) \\\n    \
+                      printf(\"%d %d\\n\", 1, \\\n           \
+                      __VA_ARGS__)\n\
+                      #define WARN(fmt, ...) printf(fmt, __VA_ARGS__)\n";
+        assert_eq!(
+            reasons(source),
+            [
+                (1, Reason::NonliteralFormat),
+                (3, Reason::VaArgs),
+                (5, Reason::NonliteralFormat),
+            ]
+        );
+    }
+
+    #[test]
+    fn member_calls_are_skipped() {
+        assert_eq!(
+            reasons("log->printf(fmt);\nq.sprintf(buf, fmt);\nprintf(fmt);"),
+            [(3, Reason::NonliteralFormat)]
+        );
+    }
+
+    #[test]
+    fn macro_formats_are_validated() {
+        let source = "#define FMT \"%d\\n\"\n\
+                      #define LOG(x) printf(FMT, x)\n\
+                      #define NAME(x) \"%d\"\n\
+                      printf(FMT, x);\n\
+                      printf(NAME(1), x);\n";
+        assert_eq!(reasons(source), [(5, Reason::NonliteralFormat)]);
+    }
+}
@@ -1,35 +1,436 @@
+use crate::function::Function;
 use crate::ir::CType;
 use crate::lex::ArgToken;
+use crate::location::{LineIndex, Location};
 use crate::parse::Arg;
 use displaydoc::Display;
-use miette::{Diagnostic, NamedSource};
+use miette::{Diagnostic, LabeledSpan, NamedSource, Severity, SourceCode};
+use serde_json::json;
+use std::fmt;
 use std::ops::Range;
 use std::path::PathBuf;
 
 /// A collections of things that went wrong while validating a file.
-#[derive(Debug, Display, Diagnostic)]
-#[displaydoc("Source code contains errors.")]
+///
+/// It's reported at the highest severity of its errors, so only as errors if
+/// any of them make the source code invalid.
+#[derive(Debug)]
 pub struct SourceErrors {
+    /// Name of the file, as it's shown in the report.
+    filename: String,
+
     /// Name and source code of the file.
-    #[source_code]
     source: NamedSource,
 
-    #[related]
-    errors: Vec<Error>,
+    errors: Vec<Reported>,
+
+    /// Location of the primary label of each error.
+    locations: Vec<Location>,
+
+    /// Start and end locations of every label of each error.
+    label_locations: Vec<Vec<(Location, Location)>>,
 }
 
 impl SourceErrors {
     /// Returns a new [`SourceErrors`]
-    pub fn new(filename: PathBuf, source: String, errors: Vec<Error>) -> Self {
+    pub fn new(filename: PathBuf, source: String, errors: Vec<Reported>) -> Self {
+        let filename = filename.to_string_lossy().into_owned();
+        let line_index = LineIndex::new(&source);
+        let locations = errors
+            .iter()
+            .map(|error| line_index.location(error.offset()))
+            .collect();
+        let label_locations = errors
+            .iter()
+            .map(|error| {
+                let labels = error.labels().into_iter().flatten();
+                labels
+                    .map(|label| line_index.span(label.offset()..label.offset() + label.len()))
+                    .collect()
+            })
+            .collect();
+
         Self {
-            source: NamedSource::new(filename.to_string_lossy(), source),
+            source: NamedSource::new(&filename, source),
+            filename,
             errors,
+            locations,
+            label_locations,
         }
     }
+
+    /// Drops errors below `min_severity`, along with their locations.
+    fn retain(&mut self, min_severity: Severity) {
+        let keep: Vec<bool> = self
+            .errors
+            .iter()
+            .map(|error| error.is_at_least(min_severity))
+            .collect();
+        let mut kept = keep.iter();
+        self.errors.retain(|_| *kept.next().expect("one per error"));
+        let mut kept = keep.iter();
+        self.locations
+            .retain(|_| *kept.next().expect("one per error"));
+        let mut kept = keep.iter();
+        self.label_locations
+            .retain(|_| *kept.next().expect("one per error"));
+    }
+
+    /// Returns the name of the file.
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// Returns `true` if any of the errors make the source code invalid.
+    pub fn has_errors(&self) -> bool {
+        self.errors.iter().any(Reported::is_error)
+    }
+
+    /// Returns the highest severity of the errors.
+    pub fn highest_severity(&self) -> Severity {
+        self.errors
+            .iter()
+            .map(|error| error.severity)
+            .min_by_key(|&severity| severity_rank(severity))
+            .unwrap_or(Severity::Advice)
+    }
+
+    /// Returns a JSON object with the filename and each of the errors, with
+    /// the line and column of their primary label.
+    pub fn to_json(&self) -> serde_json::Value {
+        let errors = self
+            .errors
+            .iter()
+            .zip(&self.locations)
+            .map(|(error, location)| {
+                let mut json = error.to_json();
+                json["line"] = json!(location.line);
+                json["column"] = json!(location.column);
+                json
+            });
+
+        json!({
+            "file": self.filename,
+            "errors": errors.collect::<Vec<_>>(),
+        })
+    }
+
+    /// Returns a SARIF `result` for each error, located at its primary label
+    /// and related to its other labels.
+    pub fn to_sarif_results(&self) -> impl Iterator<Item = serde_json::Value> + '_ {
+        self.errors
+            .iter()
+            .zip(&self.label_locations)
+            .map(|(error, label_locations)| {
+                let mut locations = error
+                    .labels()
+                    .into_iter()
+                    .flatten()
+                    .zip(label_locations)
+                    .enumerate()
+                    .map(|(id, (label, &(start, end)))| {
+                        json!({
+                            "id": id,
+                            "physicalLocation": {
+                                "artifactLocation": { "uri": self.filename },
+                                "region": {
+                                    "startLine": start.line,
+                                    "startColumn": start.column,
+                                    "endLine": end.line,
+                                    "endColumn": end.column,
+                                    "byteOffset": start.offset,
+                                    "byteLength": end.offset - start.offset,
+                                },
+                            },
+                            "message": { "text": label.label().unwrap_or_default() },
+                        })
+                    });
+                let primary = locations.next();
+
+                json!({
+                    "ruleId": error.error.rule(),
+                    "level": error.sarif_level(),
+                    "message": { "text": error.to_string() },
+                    "locations": primary.into_iter().collect::<Vec<_>>(),
+                    "relatedLocations": locations.collect::<Vec<_>>(),
+                })
+            })
+    }
+
+    /// Returns a JUnit `<testcase>` for the file, with a `<failure>` for each error.
+    pub fn to_junit(&self) -> String {
+        let mut testcase = format!(
+            "  <testcase classname=\"{}\" name=\"{}\">\n",
+            env!("CARGO_PKG_NAME"),
+            xml_escape(&self.filename)
+        );
+        for (error, location) in self.errors.iter().zip(&self.locations) {
+            testcase.push_str(&format!(
+                "    <failure type=\"{}\" message=\"{}\">{}</failure>\n",
+                xml_escape(error.error.rule()),
+                xml_escape(&error.to_string()),
+                xml_escape(&self.line(error, location))
+            ));
+        }
+        testcase.push_str("  </testcase>\n");
+        testcase
+    }
+
+    /// Returns a line for each error like `main.c:3:12: error: message [rule]`,
+    /// the way compilers report them.
+    pub fn to_short(&self) -> String {
+        self.errors
+            .iter()
+            .zip(&self.locations)
+            .map(|(error, location)| {
+                format!("{} [{}]\n", self.line(error, location), error.error.rule())
+            })
+            .collect()
+    }
+
+    /// Returns an error prefixed with where it is, like `main.c:3:12: error: message`.
+    fn line(&self, error: &Reported, location: &Location) -> String {
+        format!(
+            "{}:{}:{}: {}: {}",
+            self.filename,
+            location.line,
+            location.column,
+            severity_name(error.severity),
+            error
+        )
+    }
+}
+
+impl fmt::Display for SourceErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Source code contains {}.",
+            severity_plural(self.highest_severity())
+        )
+    }
 }
 
 impl std::error::Error for SourceErrors {}
 
+impl Diagnostic for SourceErrors {
+    fn severity(&self) -> Option<Severity> {
+        Some(self.highest_severity())
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        Some(&self.source)
+    }
+
+    fn related<'a>(&'a self) -> Option<Box<dyn Iterator<Item = &'a dyn Diagnostic> + 'a>> {
+        Some(Box::new(
+            self.errors.iter().map(|error| error as &dyn Diagnostic),
+        ))
+    }
+}
+
+/// Errors from validating many files, kept together so they can be
+/// reported at once.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    files: Vec<SourceErrors>,
+
+    /// Names of the files that were validated without errors.
+    passed: Vec<String>,
+}
+
+impl Diagnostics {
+    /// Adds the errors from validating a file, if there are any.
+    pub fn add(&mut self, filename: PathBuf, source: String, errors: Vec<Reported>) {
+        if errors.is_empty() {
+            self.pass(filename);
+        } else {
+            self.files.push(SourceErrors::new(filename, source, errors));
+        }
+    }
+
+    /// Records that a file was validated without errors.
+    pub fn pass(&mut self, filename: PathBuf) {
+        self.passed.push(filename.to_string_lossy().into_owned());
+    }
+
+    /// Drops diagnostics below `min_severity`, counting files left without
+    /// any as validated without errors.
+    pub fn retain(&mut self, min_severity: Severity) {
+        for file in &mut self.files {
+            file.retain(min_severity);
+        }
+        let (files, passed) = std::mem::take(&mut self.files)
+            .into_iter()
+            .partition(|file| !file.errors.is_empty());
+        self.files = files;
+        self.passed
+            .extend(passed.into_iter().map(|file: SourceErrors| file.filename));
+    }
+
+    /// Returns `true` if no file had errors.
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Returns `true` if any file had errors that make its source code
+    /// invalid, rather than only warnings or advice.
+    pub fn has_errors(&self) -> bool {
+        self.files.iter().any(SourceErrors::has_errors)
+    }
+
+    /// Returns `true` if any file had a diagnostic at `severity` or above.
+    pub fn has_at_least(&self, severity: Severity) -> bool {
+        self.files
+            .iter()
+            .flat_map(|file| &file.errors)
+            .any(|error| error.is_at_least(severity))
+    }
+
+    /// Returns a line counting every file's diagnostics by rule, like
+    /// `3 errors: 1 nonliteral-format, 2 excess-specifiers`, followed by
+    /// warnings and advice if there are any.
+    pub fn summary(&self) -> String {
+        let reported: Vec<&Reported> = self.files.iter().flat_map(|file| &file.errors).collect();
+
+        [
+            (Severity::Error, "error", "errors"),
+            (Severity::Warning, "warning", "warnings"),
+            (Severity::Advice, "advice", "advice"),
+        ]
+        .into_iter()
+        .filter_map(|(severity, singular, plural)| {
+            let mut counts: Vec<(&str, usize)> = Vec::new();
+            for report in reported.iter().filter(|report| report.severity == severity) {
+                let rule = report.error.rule();
+                match counts.iter_mut().find(|(name, _)| *name == rule) {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((rule, 1)),
+                }
+            }
+            // built-in rules in the order they're declared, then lints by name
+            counts.sort_by_key(|&(rule, _)| {
+                let position = Error::RULES.iter().position(|&name| name == rule);
+                (position.unwrap_or(Error::RULES.len()), rule)
+            });
+
+            let total: usize = counts.iter().map(|(_, count)| count).sum();
+            let noun = if total == 1 { singular } else { plural };
+            let counts: Vec<String> = counts
+                .iter()
+                .map(|(rule, count)| format!("{count} {rule}"))
+                .collect();
+            (total > 0).then(|| format!("{total} {noun}: {}", counts.join(", ")))
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+    }
+
+    /// Returns the highest severity of the errors in any file, or `None`
+    /// if no file had errors.
+    pub fn highest_severity(&self) -> Option<Severity> {
+        self.files
+            .iter()
+            .map(SourceErrors::highest_severity)
+            .min_by_key(|&severity| severity_rank(severity))
+    }
+
+    /// Returns the errors of each file that had errors, in the order they were added.
+    pub fn into_files(self) -> Vec<SourceErrors> {
+        self.files
+    }
+
+    /// Renders every file's errors as one report, without an extra layer
+    /// of nesting when there's only one file.
+    pub fn render(mut self) -> miette::Report {
+        match self.files.len() {
+            1 => self.files.pop().expect("length is 1").into(),
+            _ => self.into(),
+        }
+    }
+
+    /// Returns a JSON array with an object for each file that had errors.
+    pub fn to_json(&self) -> serde_json::Value {
+        self.files.iter().map(SourceErrors::to_json).collect()
+    }
+
+    /// Returns a line for each error of every file, like `main.c:3:12: error: message [rule]`.
+    pub fn to_short(&self) -> String {
+        self.files.iter().map(SourceErrors::to_short).collect()
+    }
+
+    /// Returns a SARIF 2.1.0 log with a single run, with a result for each
+    /// error of every file.
+    pub fn to_sarif(&self) -> serde_json::Value {
+        let rules = Error::RULES.iter().map(|rule| json!({ "id": rule }));
+        let results = self.files.iter().flat_map(SourceErrors::to_sarif_results);
+
+        json!({
+            "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": env!("CARGO_PKG_NAME"),
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "rules": rules.collect::<Vec<_>>(),
+                    },
+                },
+                "columnKind": "unicodeCodePoints",
+                "results": results.collect::<Vec<_>>(),
+            }],
+        })
+    }
+
+    /// Returns a JUnit XML `<testsuite>` with a `<testcase>` for each file
+    /// that was validated, and a `<failure>` for each of their errors.
+    pub fn to_junit(&self) -> String {
+        let failures: usize = self.files.iter().map(|file| file.errors.len()).sum();
+        let mut xml = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuite name=\"{}\" tests=\"{}\" failures=\"{failures}\">\n",
+            env!("CARGO_PKG_NAME"),
+            self.files.len() + self.passed.len(),
+        );
+        for file in &self.files {
+            xml.push_str(&file.to_junit());
+        }
+        for filename in &self.passed {
+            xml.push_str(&format!(
+                "  <testcase classname=\"{}\" name=\"{}\"/>\n",
+                env!("CARGO_PKG_NAME"),
+                xml_escape(filename)
+            ));
+        }
+        xml.push_str("</testsuite>\n");
+        xml
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = self.highest_severity().unwrap_or(Severity::Error);
+        write!(
+            f,
+            "Source code in multiple files contains {}.",
+            severity_plural(severity)
+        )
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+impl Diagnostic for Diagnostics {
+    fn severity(&self) -> Option<Severity> {
+        self.highest_severity()
+    }
+
+    fn related<'a>(&'a self) -> Option<Box<dyn Iterator<Item = &'a dyn Diagnostic> + 'a>> {
+        Some(Box::new(
+            self.files.iter().map(|file| file as &dyn Diagnostic),
+        ))
+    }
+}
+
 /// Error that may occur during validation.
 #[derive(Debug, Display, Diagnostic)]
 pub enum Error {
@@ -37,6 +438,14 @@ pub enum Error {
     #[diagnostic(help("Supply enough arguments for the function call."))]
     MissingFunctionArgs(#[label("not enough arguments in function call")] Range<usize>),
 
+    /// Empty argument in function call.
+    #[diagnostic(help("Remove the extra `,`."))]
+    EmptyArgument(#[label("empty argument next to this `,`")] Range<usize>),
+
+    /// Function call is never closed.
+    #[diagnostic(help("Add a `)` to close the call."))]
+    UnterminatedCall(#[label("this `(` is never closed")] Range<usize>),
+
     /// Format string isn't a string literal, this is potentially an overflow vulnerability!
     NonliteralFormat {
         #[label("not a string literal")]
@@ -45,8 +454,45 @@ pub enum Error {
         help: String,
     },
 
+    /// Unsupported conversion in format string.
+    UnknownConversion {
+        #[label("unknown conversion `{conversion}`")]
+        span: Range<usize>,
+        conversion: String,
+        #[help]
+        help: String,
+    },
+
+    /// Specifier isn't in the C standard being validated against.
+    #[diagnostic(help(
+        "Pass `--c-standard c23` if your compiler supports C23, or print in hexadecimal with `%x` instead."
+    ))]
+    UnsupportedSpecifier(#[label("binary specifiers need C23")] Range<usize>),
+
+    /// Specifier is split across adjacent string literals, which isn't supported.
+    #[diagnostic(help(
+        "Write the whole specifier in one string literal, e.g. `\"%d\"` instead of `\"%\" \"d\"`."
+    ))]
+    SplitSpecifier(#[label("specifier continues in the next string literal")] Range<usize>),
+
+    /// Invalid escape sequence in format string.
+    #[diagnostic(help(
+        "Use an escape like `\\n`, `\\\\`, or `\\\"`, an octal one up to `\\377`, or a hexadecimal one up to `\\xff`."
+    ))]
+    InvalidEscape {
+        #[label("{reason}")]
+        span: Range<usize>,
+        reason: &'static str,
+    },
+
+    /// `%n` writes to memory, this is a classic format string exploit!
+    #[diagnostic(help(
+        "`%n` is banned, use the return value of the call to count the characters printed instead."
+    ))]
+    WriteBackSpecifier(#[label("writes the number of characters printed so far")] Range<usize>),
+
     /// Incorrect specifier for type casted argument.
-    #[diagnostic(help("Change the specifier to `%{}`, or change the cast to `({specifier_ctype})`.", cast_ctype.specifier_char()))]
+    #[diagnostic(help("Change the specifier to `%{}`, or change the cast to `({specifier_ctype})`.", cast_ctype.specifier()))]
     SpecifierCastMismatch {
         #[label("format string expects `{specifier_ctype}` value")]
         specifier_span: Range<usize>,
@@ -57,8 +503,166 @@ pub enum Error {
         cast_ctype: CType,
     },
 
+    /// Incorrect specifier for argument.
+    #[diagnostic(help("Change the specifier to `%{}`.", arg_ctype.specifier()))]
+    SpecifierArgMismatch {
+        #[label("format string expects `{specifier_ctype}` value")]
+        specifier_span: Range<usize>,
+        specifier_ctype: CType,
+
+        #[label("argument is `{arg_ctype}`")]
+        arg_span: Range<usize>,
+        arg_ctype: CType,
+    },
+
+    /// Width or precision argument isn't an `int`.
+    #[diagnostic(help("A `*` width or precision takes an `int`, cast the argument to `(int)`."))]
+    StarArgMismatch {
+        #[label("expects `int` argument")]
+        star_span: Range<usize>,
+
+        #[label("argument is `{arg_ctype}`")]
+        arg_span: Range<usize>,
+        arg_ctype: CType,
+    },
+
+    /// Specifiers mix positional and sequential arguments, which is undefined behavior.
+    #[diagnostic(help("Give every specifier a position like `%1$d`, or none of them."))]
+    MixedPositions {
+        #[label("references its argument by position")]
+        positional_span: Range<usize>,
+
+        #[label("takes its argument in order")]
+        sequential_span: Range<usize>,
+    },
+
+    /// Specifier references an argument that doesn't exist.
+    #[diagnostic(help("Positions start at 1, and there {}.", match args {
+        1 => "is 1 argument".to_string(),
+        n => format!("are {n} arguments"),
+    }))]
+    MissingPosition {
+        #[label("references argument {position}")]
+        specifier_span: Range<usize>,
+        position: usize,
+
+        #[label("arguments")]
+        args_span: Range<usize>,
+        args: usize,
+    },
+
+    /// Width or precision from an argument can't be optimized.
+    #[diagnostic(help(
+        "Optimized calls don't take widths or precisions, use `--typecast` for this call instead."
+    ))]
+    UnoptimizableStar(#[label("`--optimize` would drop this argument")] Range<usize>),
+
+    /// Incorrect specifier for the return type of the called function.
+    #[diagnostic(help("Change the specifier to `%{}`, or cast the argument to `({specifier_ctype})`.", return_ctype.specifier()))]
+    SpecifierReturnMismatch {
+        #[label("format string expects `{specifier_ctype}` value")]
+        specifier_span: Range<usize>,
+        specifier_ctype: CType,
+
+        #[label("function returns `{return_ctype}`")]
+        call_span: Range<usize>,
+        return_ctype: CType,
+    },
+
+    /// Redundant type cast, the argument already has this type.
+    #[diagnostic(severity(Warning), help("Remove the `({ctype})` cast."))]
+    RedundantCast {
+        #[label("argument is already `{ctype}`")]
+        cast_span: Range<usize>,
+        ctype: CType,
+    },
+
+    /// Type cast matches the specifier exactly, it's only needed if the argument has another type.
+    #[diagnostic(
+        severity(Advice),
+        help("Remove the `({ctype})` cast if the argument is already `{ctype}`.")
+    )]
+    CastMatchesSpecifier {
+        #[label("formatted as `{ctype}`")]
+        specifier_span: Range<usize>,
+
+        #[label("casted to the same type")]
+        cast_span: Range<usize>,
+        ctype: CType,
+    },
+
+    /// Redundant length modifier, `%f` and the like already format a `double`.
+    #[diagnostic(
+        severity(Advice),
+        help("Remove the `l`, `float` arguments are promoted to `double` anyway.")
+    )]
+    RedundantLengthModifier(#[label("`l` has no effect here")] Range<usize>),
+
+    /// Specifier is followed by its own conversion, which is printed as text.
+    #[diagnostic(
+        severity(Advice),
+        help("Remove the second `{conversion}` if it's a typo.")
+    )]
+    RepeatedConversion {
+        #[label("this `{conversion}` is printed as is")]
+        span: Range<usize>,
+        conversion: char,
+    },
+
+    /// Argument is nested too deeply.
+    #[diagnostic(help("Simplify the argument, or raise the limit with `--max-nesting`."))]
+    ExcessiveNesting {
+        #[label("nested more than {max_nesting} parentheses deep")]
+        span: Range<usize>,
+        max_nesting: usize,
+    },
+
+    /// Buffer isn't a pointer.
+    #[diagnostic(help("Pass a `char*` buffer to write the formatted string to."))]
+    NonPointerBuffer {
+        #[label("`{ctype}` isn't a pointer")]
+        span: Range<usize>,
+        ctype: CType,
+    },
+
+    /// Buffer is a string literal, which can't be written to.
+    #[diagnostic(help(
+        "Pass a `char*` buffer like a `char` array to write the formatted string to."
+    ))]
+    LiteralBuffer(#[label("string literals are read-only")] Range<usize>),
+
+    /// Out-pointer isn't a pointer.
+    #[diagnostic(help(
+        "Pass the address of a `char*` to store the formatted string in, like `&buf`."
+    ))]
+    NonPointerOut {
+        #[label("`{ctype}` isn't a pointer")]
+        span: Range<usize>,
+        ctype: CType,
+    },
+
+    /// Buffer size isn't an integer.
+    #[diagnostic(help("Pass the size of the buffer as a `size_t`."))]
+    NonIntegerSize {
+        #[label("`{ctype}` isn't an integer")]
+        span: Range<usize>,
+        ctype: CType,
+    },
+
+    /// Buffer is too small for the format string, the output will be truncated.
+    #[diagnostic(severity(Warning), help("Make the buffer at least {}.", bytes(*required)))]
+    BufferTooSmall {
+        #[label("buffer holds {}", bytes(*bufsz))]
+        bufsz_span: Range<usize>,
+        bufsz: u64,
+
+        #[label("needs at least {}", bytes(*required))]
+        format_span: Range<usize>,
+        required: u64,
+    },
+
     /// Excess specifiers, this will read arbitrary data off the stack!
-    #[diagnostic(help("{}", help_excess_specifiers(*additional_specifiers)))]
+    #[diagnostic(help("{}", help_excess_specifiers(*additional_specifiers, *missing_args, *function)))]
     ExcessSpecifiers {
         #[label("{additional_specifiers} too many specifiers")]
         format_span: Range<usize>,
@@ -66,10 +670,28 @@ pub enum Error {
         #[label("not enough arguments")]
         args_span: Range<usize>,
         additional_specifiers: usize,
+        /// Arguments the specifiers are missing, counting ones for `*` widths and precisions.
+        missing_args: usize,
+        function: Function,
+    },
+
+    /// {message}
+    Lint {
+        #[label("{label}")]
+        span: Range<usize>,
+        label: String,
+        /// Name of the rule, e.g. `no-float-in-logs`.
+        rule: &'static str,
+        message: String,
+        #[help]
+        help: Option<String>,
     },
 
     /// Excess arguments.
-    #[diagnostic(help("{}", help_excess_args(*additional_args)))]
+    #[diagnostic(
+        severity(Warning),
+        help("{}", help_excess_args(*additional_args, *function))
+    )]
     ExcessArgs {
         #[label("not enough specifiers")]
         format_span: Range<usize>,
@@ -77,17 +699,42 @@ pub enum Error {
         #[label("{additional_args} too many arguments")]
         args_span: Range<usize>,
         additional_args: usize,
+        function: Function,
+    },
+
+    /// Format string is empty, but arguments are passed to it.
+    #[diagnostic(
+        severity(Warning),
+        help("Remove the arguments, an empty format string never prints them.")
+    )]
+    EmptyFormatWithArgs {
+        #[label("empty format string")]
+        format_span: Range<usize>,
+
+        #[label("never printed")]
+        args_span: Range<usize>,
     },
 }
 
 impl Error {
-    pub fn nonliteral(arg: Arg<'_>) -> Self {
+    pub fn nonliteral(arg: Arg<'_>, literal_ternary: bool) -> Self {
         Self::NonliteralFormat {
             span: arg.span,
             help: match arg.single_token {
+                _ if literal_ternary => {
+                    "Both branches are string literals, but only whole calls can be validated, select between whole calls instead."
+                        .to_string()
+                }
                 Some(ArgToken::Identifier(ident)) => {
                     format!(r#"To safely print a string, use `printf("%s", {ident})` instead."#)
                 }
+                Some(ArgToken::RawString(_)) => {
+                    "Raw string literals are only supported with `--cpp`.".to_string()
+                }
+                _ if arg.first_token == Some(ArgToken::Identifier("_Generic")) => {
+                    "Formats selected by `_Generic` can't be validated statically, select between whole calls instead."
+                        .to_string()
+                }
                 _ => r#"Use a string literal as the first argument, like `printf("hello")`."#
                     .to_string(),
             },
@@ -95,20 +742,684 @@ impl Error {
     }
 }
 
+impl Error {
+    pub fn unknown_conversion(conversion: &str, span: Range<usize>) -> Self {
+        let help = match conversion.chars().last() {
+            _ if conversion == "%" => "Use `%%` to print a percent sign.".to_string(),
+            Some('C' | 'S') => format!("`{conversion}` is only recognized with `--ms-compat`."),
+            _ => "Use one of `%d`, `%i`, `%ld`, `%lld`, `%zu`, `%u`, `%x`, `%X`, `%o`, `%c`, `%lc`, `%f`, `%e`, `%g`, `%Lf`, `%s`, `%ls`, or `%p`.".to_string(),
+        };
+
+        Self::UnknownConversion {
+            span,
+            conversion: conversion.to_string(),
+            help,
+        }
+    }
+}
+
+impl Error {
+    /// Returns the byte offset of the primary label.
+    pub fn offset(&self) -> usize {
+        match self {
+            Error::MissingFunctionArgs(span) => span.start,
+            Error::EmptyArgument(span) => span.start,
+            Error::UnterminatedCall(span) => span.start,
+            Error::NonliteralFormat { span, .. } => span.start,
+            Error::UnknownConversion { span, .. } => span.start,
+            Error::UnsupportedSpecifier(span) => span.start,
+            Error::SplitSpecifier(span) => span.start,
+            Error::InvalidEscape { span, .. } => span.start,
+            Error::WriteBackSpecifier(span) => span.start,
+            Error::SpecifierCastMismatch { specifier_span, .. } => specifier_span.start,
+            Error::SpecifierArgMismatch { specifier_span, .. } => specifier_span.start,
+            Error::StarArgMismatch { star_span, .. } => star_span.start,
+            Error::MixedPositions {
+                positional_span, ..
+            } => positional_span.start,
+            Error::MissingPosition { specifier_span, .. } => specifier_span.start,
+            Error::UnoptimizableStar(span) => span.start,
+            Error::SpecifierReturnMismatch { specifier_span, .. } => specifier_span.start,
+            Error::RedundantCast { cast_span, .. } => cast_span.start,
+            Error::CastMatchesSpecifier { specifier_span, .. } => specifier_span.start,
+            Error::RedundantLengthModifier(span) => span.start,
+            Error::RepeatedConversion { span, .. } => span.start,
+            Error::ExcessiveNesting { span, .. } => span.start,
+            Error::NonPointerBuffer { span, .. } => span.start,
+            Error::LiteralBuffer(span) => span.start,
+            Error::NonPointerOut { span, .. } => span.start,
+            Error::NonIntegerSize { span, .. } => span.start,
+            Error::BufferTooSmall { bufsz_span, .. } => bufsz_span.start,
+            Error::ExcessSpecifiers { format_span, .. } => format_span.start,
+            Error::Lint { span, .. } => span.start,
+            Error::ExcessArgs { format_span, .. } => format_span.start,
+            Error::EmptyFormatWithArgs { format_span, .. } => format_span.start,
+        }
+    }
+}
+
+impl Error {
+    /// Names of the built-in rules that errors can be reported under, see [`Error::rule`].
+    pub const RULES: &'static [&'static str] = &[
+        "missing-function-args",
+        "empty-argument",
+        "unterminated-call",
+        "nonliteral-format",
+        "unknown-conversion",
+        "unsupported-specifier",
+        "split-specifier",
+        "invalid-escape",
+        "write-back-specifier",
+        "specifier-cast-mismatch",
+        "specifier-arg-mismatch",
+        "star-arg-mismatch",
+        "mixed-positions",
+        "missing-position",
+        "unoptimizable-star",
+        "specifier-return-mismatch",
+        "redundant-cast",
+        "cast-matches-specifier",
+        "redundant-length-modifier",
+        "repeated-conversion",
+        "excessive-nesting",
+        "non-pointer-buffer",
+        "literal-buffer",
+        "non-pointer-out",
+        "non-integer-size",
+        "buffer-too-small",
+        "excess-specifiers",
+        "excess-args",
+        "empty-format-with-args",
+    ];
+
+    /// Returns the name of the rule the error is reported under, e.g. `excess-args`.
+    pub fn rule(&self) -> &'static str {
+        match self {
+            Error::MissingFunctionArgs(_) => "missing-function-args",
+            Error::EmptyArgument(_) => "empty-argument",
+            Error::UnterminatedCall(_) => "unterminated-call",
+            Error::NonliteralFormat { .. } => "nonliteral-format",
+            Error::UnknownConversion { .. } => "unknown-conversion",
+            Error::UnsupportedSpecifier(_) => "unsupported-specifier",
+            Error::SplitSpecifier(_) => "split-specifier",
+            Error::InvalidEscape { .. } => "invalid-escape",
+            Error::WriteBackSpecifier(_) => "write-back-specifier",
+            Error::SpecifierCastMismatch { .. } => "specifier-cast-mismatch",
+            Error::SpecifierArgMismatch { .. } => "specifier-arg-mismatch",
+            Error::StarArgMismatch { .. } => "star-arg-mismatch",
+            Error::MixedPositions { .. } => "mixed-positions",
+            Error::MissingPosition { .. } => "missing-position",
+            Error::UnoptimizableStar(_) => "unoptimizable-star",
+            Error::SpecifierReturnMismatch { .. } => "specifier-return-mismatch",
+            Error::RedundantCast { .. } => "redundant-cast",
+            Error::CastMatchesSpecifier { .. } => "cast-matches-specifier",
+            Error::RedundantLengthModifier(_) => "redundant-length-modifier",
+            Error::RepeatedConversion { .. } => "repeated-conversion",
+            Error::ExcessiveNesting { .. } => "excessive-nesting",
+            Error::NonPointerBuffer { .. } => "non-pointer-buffer",
+            Error::LiteralBuffer(_) => "literal-buffer",
+            Error::NonPointerOut { .. } => "non-pointer-out",
+            Error::NonIntegerSize { .. } => "non-integer-size",
+            Error::BufferTooSmall { .. } => "buffer-too-small",
+            Error::ExcessSpecifiers { .. } => "excess-specifiers",
+            Error::Lint { rule, .. } => rule,
+            Error::ExcessArgs { .. } => "excess-args",
+            Error::EmptyFormatWithArgs { .. } => "empty-format-with-args",
+        }
+    }
+
+    /// Returns `true` if the error can make the program exploitable, e.g. by
+    /// reading or writing memory through the format string.
+    pub fn is_exploitable(&self) -> bool {
+        matches!(
+            self,
+            Error::NonliteralFormat { .. }
+                | Error::WriteBackSpecifier(_)
+                | Error::ExcessSpecifiers { .. }
+        )
+    }
+}
+
 impl std::error::Error for Error {}
 
-fn help_excess_args(count: usize) -> String {
-    if count == 1 {
-        "Add a specifier or remove an argument.".to_string()
-    } else {
-        format!("Add {count} specifiers or remove {count} arguments.")
+/// An [`Error`] tagged with the severity it's reported at, which is the
+/// severity of its variant unless its rule was overridden in a
+/// [`RuleConfig`](crate::rules::RuleConfig).
+#[derive(Debug)]
+pub struct Reported {
+    error: Error,
+    severity: Severity,
+}
+
+impl Reported {
+    /// Returns a new [`Reported`].
+    pub fn new(error: Error, severity: Severity) -> Self {
+        Self { error, severity }
+    }
+
+    /// Returns the underlying error.
+    pub fn error(&self) -> &Error {
+        &self.error
+    }
+
+    /// Returns the byte offset of the primary label.
+    pub fn offset(&self) -> usize {
+        self.error.offset()
+    }
+
+    /// Returns `true` if the error is reported with error severity, rather
+    /// than as a warning or advice.
+    pub fn is_error(&self) -> bool {
+        matches!(self.severity, Severity::Error)
+    }
+
+    /// Returns `true` if the error is reported at `severity` or above, e.g.
+    /// errors and warnings for [`Severity::Warning`].
+    pub fn is_at_least(&self, severity: Severity) -> bool {
+        severity_rank(self.severity) <= severity_rank(severity)
+    }
+
+    /// Returns the SARIF `level` of the error.
+    ///
+    /// Only errors that make the program exploitable, like a nonliteral
+    /// format string, are SARIF errors. Other errors are SARIF warnings, and
+    /// advice is a SARIF note.
+    pub fn sarif_level(&self) -> &'static str {
+        match self.severity {
+            Severity::Error if self.error.is_exploitable() => "error",
+            Severity::Error | Severity::Warning => "warning",
+            Severity::Advice => "note",
+        }
+    }
+
+    /// Returns a JSON object with the message, rule, severity, labeled byte
+    /// spans, and help of the error.
+    pub fn to_json(&self) -> serde_json::Value {
+        let labels = self.labels().into_iter().flatten().map(|label| {
+            json!({
+                "label": label.label(),
+                "start": label.offset(),
+                "end": label.offset() + label.len(),
+            })
+        });
+
+        json!({
+            "message": self.to_string(),
+            "rule": self.error.rule(),
+            "severity": severity_name(self.severity),
+            "labels": labels.collect::<Vec<_>>(),
+            "help": self.help().map(|help| help.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for Reported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl std::error::Error for Reported {}
+
+impl Diagnostic for Reported {
+    fn severity(&self) -> Option<Severity> {
+        Some(self.severity)
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.error.help()
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        self.error.labels()
+    }
+}
+
+/// Sorts errors before warnings before advice, each in the order they appear in the source.
+pub fn sort_by_severity(errors: &mut [Reported]) {
+    errors.sort_by_key(|error| (severity_rank(error.severity), error.offset()));
+}
+
+/// Ranks errors before warnings before advice.
+fn severity_rank(severity: Severity) -> u8 {
+    match severity {
+        Severity::Error => 0,
+        Severity::Warning => 1,
+        Severity::Advice => 2,
     }
 }
 
-fn help_excess_specifiers(count: usize) -> String {
+fn severity_plural(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "errors",
+        Severity::Warning => "warnings",
+        Severity::Advice => "advice",
+    }
+}
+
+fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Advice => "advice",
+    }
+}
+
+/// Escapes the characters that are special in XML text and attributes.
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn help_excess_args(count: usize, function: Function) -> String {
+    let name = function.name();
     if count == 1 {
-        "Add an argument or remove a specifier.".to_string()
+        format!("Add a specifier or remove an argument from this `{name}` call.")
     } else {
-        format!("Add {count} arguments or remove {count} specifiers.")
+        format!("Add {count} specifiers or remove {count} arguments from this `{name}` call.")
+    }
+}
+
+fn bytes(count: u64) -> String {
+    match count {
+        1 => "1 byte".to_string(),
+        n => format!("{n} bytes"),
+    }
+}
+
+fn help_excess_specifiers(count: usize, missing_args: usize, function: Function) -> String {
+    let name = function.name();
+    let arguments = match missing_args {
+        1 => "an argument".to_string(),
+        n => format!("{n} arguments"),
+    };
+    let specifiers = match count {
+        1 => "a specifier".to_string(),
+        n => format!("{n} specifiers"),
+    };
+    format!("Add {arguments} to this `{name}` call or remove {specifiers}.")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::ir::IntermediateRepresentation;
+    use miette::{GraphicalReportHandler, GraphicalTheme};
+
+    /// Returns the help of the first error in source code.
+    fn help(source: &str) -> String {
+        IntermediateRepresentation::parse(source, &Config::default())
+            .expect_err("source code is invalid")[0]
+            .help()
+            .expect("error has help")
+            .to_string()
+    }
+
+    #[test]
+    fn nonliteral_generic_help() {
+        assert_eq!(
+            help(r#"printf(_Generic(x, int: "%d\n", default: "%s\n"), x);"#),
+            "Formats selected by `_Generic` can't be validated statically, select between whole calls instead."
+        );
+        assert_eq!(
+            help("printf(fmt, x);"),
+            r#"To safely print a string, use `printf("%s", fmt)` instead."#
+        );
+        assert_eq!(
+            help("printf(get_format(), x);"),
+            r#"Use a string literal as the first argument, like `printf("hello")`."#
+        );
+    }
+
+    #[test]
+    fn warnings_only_are_reported_as_warnings() {
+        let config = Config {
+            warn_redundant_casts: true,
+            ..Config::default()
+        };
+        let source = r#"printf("%d", (int) 5);"#;
+        let warnings = IntermediateRepresentation::parse(source, &config)
+            .expect("source code is valid")
+            .into_warnings();
+        let warnings = SourceErrors::new("main.c".into(), source.to_string(), warnings);
+        assert_eq!(warnings.to_string(), "Source code contains warnings.");
+        assert_eq!(warnings.severity(), Some(Severity::Warning));
+
+        let source = r#"printf("%s", (int) 5);"#;
+        let errors = IntermediateRepresentation::parse(source, &config).unwrap_err();
+        let errors = SourceErrors::new("main.c".into(), source.to_string(), errors);
+        assert_eq!(errors.to_string(), "Source code contains errors.");
+        assert_eq!(errors.severity(), Some(Severity::Error));
+    }
+
+    #[test]
+    fn sort_by_severity_then_offset() {
+        let mut errors = vec![
+            Reported::new(Error::MissingFunctionArgs(10..11), Severity::Warning),
+            Reported::new(Error::MissingFunctionArgs(20..21), Severity::Error),
+            Reported::new(Error::MissingFunctionArgs(5..6), Severity::Warning),
+            Reported::new(Error::MissingFunctionArgs(15..16), Severity::Error),
+        ];
+        sort_by_severity(&mut errors);
+
+        let sorted: Vec<(bool, usize)> = errors
+            .iter()
+            .map(|error| (error.is_error(), error.offset()))
+            .collect();
+        assert_eq!(sorted, [(true, 15), (true, 20), (false, 5), (false, 10)]);
+    }
+
+    #[test]
+    fn diagnostics_merge_two_files() {
+        let errors = |source: &str| {
+            IntermediateRepresentation::parse(source, &Config::default())
+                .expect_err("source code is invalid")
+        };
+        let main = "printf(\"%d\");\n";
+        let util = "printf(\"%s\", (int) x);\n";
+        let mut diagnostics = Diagnostics::default();
+        diagnostics.add("main.c".into(), main.to_string(), errors(main));
+        diagnostics.add("util.c".into(), util.to_string(), errors(util));
+        diagnostics.add("ok.c".into(), String::new(), Vec::new());
+        assert!(!diagnostics.is_empty());
+
+        let mut rendered = String::new();
+        GraphicalReportHandler::new_themed(GraphicalTheme::unicode_nocolor())
+            .render_report(&mut rendered, diagnostics.render().as_ref())
+            .unwrap();
+        assert!(
+            rendered.starts_with("\n  × Source code in multiple files contains errors."),
+            "{rendered}"
+        );
+        assert!(rendered.contains("[main.c:1:1]"), "{rendered}");
+        assert!(rendered.contains("[util.c:1:1]"), "{rendered}");
+        assert!(!rendered.contains("ok.c"), "{rendered}");
+    }
+
+    #[test]
+    fn unknown_conversion_help() {
+        assert_eq!(
+            help(r#"printf("%q", x);"#),
+            "Use one of `%d`, `%i`, `%ld`, `%lld`, `%zu`, `%u`, `%x`, `%X`, `%o`, `%c`, `%lc`, `%f`, `%e`, `%g`, `%Lf`, `%s`, `%ls`, or `%p`."
+        );
+        assert_eq!(
+            help(r#"printf("%S", s);"#),
+            "`%S` is only recognized with `--ms-compat`."
+        );
+    }
+
+    #[test]
+    fn junit_has_failure_for_each_error() {
+        let source = "printf(\"%d\");\nprintf(\"%s\", 5);\n";
+        let errors = IntermediateRepresentation::parse(source, &Config::default())
+            .expect_err("source code is invalid");
+        let mut diagnostics = Diagnostics::default();
+        diagnostics.add("main.c".into(), source.to_string(), errors);
+        diagnostics.pass("ok.c".into());
+
+        assert_eq!(
+            diagnostics.to_junit(),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<testsuite name=\"safe_printf\" tests=\"2\" failures=\"2\">
+  <testcase classname=\"safe_printf\" name=\"main.c\">
+    <failure type=\"excess-specifiers\" message=\"Excess specifiers, this will read arbitrary data off the stack!\">main.c:1:8: error: Excess specifiers, this will read arbitrary data off the stack!</failure>
+    <failure type=\"specifier-arg-mismatch\" message=\"Incorrect specifier for argument.\">main.c:2:9: error: Incorrect specifier for argument.</failure>
+  </testcase>
+  <testcase classname=\"safe_printf\" name=\"ok.c\"/>
+</testsuite>
+"
+        );
+    }
+
+    #[test]
+    fn junit_escapes_special_characters() {
+        assert_eq!(
+            xml_escape(r#"Don't use "quotes" & <brackets>."#),
+            "Don&apos;t use &quot;quotes&quot; &amp; &lt;brackets&gt;."
+        );
+
+        let mut diagnostics = Diagnostics::default();
+        diagnostics.pass("a&b.c".into());
+        assert!(diagnostics
+            .to_junit()
+            .contains("<testcase classname=\"safe_printf\" name=\"a&amp;b.c\"/>"));
+    }
+
+    #[test]
+    fn excess_help_names_function() {
+        assert_eq!(
+            help(r#"snprintf(buf, sizeof buf, "%d %d", x);"#),
+            "Add an argument to this `snprintf` call or remove a specifier."
+        );
+        let warnings = IntermediateRepresentation::parse(
+            r#"sprintf(buf, "%d", x, y, z);"#,
+            &Config::default(),
+        )
+        .expect("excess args are only a warning")
+        .into_warnings();
+        assert_eq!(
+            warnings[0].help().expect("warning has help").to_string(),
+            "Add 2 specifiers or remove 2 arguments from this `sprintf` call."
+        );
+    }
+
+    #[test]
+    fn lone_percent_help() {
+        assert_eq!(
+            help(r#"printf("100%");"#),
+            "Use `%%` to print a percent sign."
+        );
+    }
+
+    #[test]
+    fn excess_help_counts_star_args() {
+        assert_eq!(
+            help(r#"printf("%*.*f", w);"#),
+            "Add 2 arguments to this `printf` call or remove a specifier."
+        );
+        assert_eq!(
+            help(r#"printf("%*d %d", w);"#),
+            "Add 2 arguments to this `printf` call or remove 2 specifiers."
+        );
+        assert_eq!(
+            help(r#"printf("%*d", 1.5, x);"#),
+            "A `*` width or precision takes an `int`, cast the argument to `(int)`."
+        );
+    }
+
+    #[test]
+    fn missing_position_help_counts_args() {
+        assert_eq!(
+            help(r#"printf("%1$d %3$d", x, y);"#),
+            "Positions start at 1, and there are 2 arguments."
+        );
+        assert_eq!(
+            help(r#"printf("%2$d", x);"#),
+            "Positions start at 1, and there is 1 argument."
+        );
+    }
+
+    #[test]
+    fn json_matches_documented_schema() {
+        let source = "int main() {\n    printf(\"%s\\n\", 42);\n}\n";
+        let errors = IntermediateRepresentation::parse(source, &Config::default())
+            .expect_err("source code is invalid");
+        let mut diagnostics = Diagnostics::default();
+        diagnostics.add("main.c".into(), source.to_string(), errors);
+
+        let json = diagnostics.to_json();
+        let error = &json[0]["errors"][0];
+        assert_eq!(json[0]["file"], "main.c");
+        assert_eq!(error["rule"], "specifier-arg-mismatch");
+        assert_eq!(error["severity"], "error");
+        assert_eq!(error["help"], "Change the specifier to `%d`.");
+        assert_eq!((&error["line"], &error["column"]), (&2.into(), &13.into()));
+        assert_eq!(
+            (&error["labels"][0]["start"], &error["labels"][0]["end"]),
+            (&25.into(), &27.into())
+        );
+    }
+
+    #[test]
+    fn excess_args_alone_are_not_errors() {
+        let source = "printf(\"%d\", x, y);\n";
+        let warnings = IntermediateRepresentation::parse(source, &Config::default())
+            .expect("excess args are only a warning")
+            .into_warnings();
+        let mut diagnostics = Diagnostics::default();
+        diagnostics.add("main.c".into(), source.to_string(), warnings);
+
+        assert!(!diagnostics.is_empty());
+        assert!(!diagnostics.has_errors());
+        assert_eq!(diagnostics.highest_severity(), Some(Severity::Warning));
+        assert_eq!(
+            diagnostics.render().to_string(),
+            "Source code contains warnings."
+        );
+    }
+
+    #[test]
+    fn retain_hides_diagnostics_below_a_severity() {
+        let source = "printf(\"%d\", x, y);\n";
+        let warnings = IntermediateRepresentation::parse(source, &Config::default())
+            .expect("excess args are only a warning")
+            .into_warnings();
+        let mut diagnostics = Diagnostics::default();
+        diagnostics.add("main.c".into(), source.to_string(), warnings);
+        assert!(diagnostics.has_at_least(Severity::Warning));
+        assert!(!diagnostics.has_at_least(Severity::Error));
+
+        diagnostics.retain(Severity::Warning);
+        assert!(!diagnostics.is_empty());
+        diagnostics.retain(Severity::Error);
+        assert!(diagnostics.is_empty());
+        assert!(!diagnostics.has_at_least(Severity::Advice));
+        // the file is counted as passed
+        assert!(diagnostics
+            .to_junit()
+            .contains("<testcase classname=\"safe_printf\" name=\"main.c\"/>"));
+    }
+
+    #[test]
+    fn errors_outrank_warnings() {
+        let mut diagnostics = Diagnostics::default();
+        assert_eq!(diagnostics.highest_severity(), None);
+
+        let source = "printf(\"%d\", x, y);\n";
+        let warnings = IntermediateRepresentation::parse(source, &Config::default())
+            .expect("excess args are only a warning")
+            .into_warnings();
+        diagnostics.add("warn.c".into(), source.to_string(), warnings);
+        let source = "printf(\"%d\");\n";
+        let errors = IntermediateRepresentation::parse(source, &Config::default())
+            .expect_err("source code is invalid");
+        diagnostics.add("error.c".into(), source.to_string(), errors);
+
+        assert!(diagnostics.has_errors());
+        assert_eq!(diagnostics.highest_severity(), Some(Severity::Error));
+        assert_eq!(
+            diagnostics.to_string(),
+            "Source code in multiple files contains errors."
+        );
+    }
+
+    #[test]
+    fn sarif_locates_each_result() {
+        let source = "int x;\nprintf(x);\nprintf(\"%s\", 5);\n";
+        let errors = IntermediateRepresentation::parse(source, &Config::default())
+            .expect_err("source code is invalid");
+        let mut diagnostics = Diagnostics::default();
+        diagnostics.add("main.c".into(), source.to_string(), errors);
+
+        let sarif = diagnostics.to_sarif();
+        assert_eq!(sarif["version"], "2.1.0");
+        let run = &sarif["runs"][0];
+        assert_eq!(run["tool"]["driver"]["name"], "safe_printf");
+        let results = run["results"].as_array().unwrap();
+        assert_eq!(results.len(), 2);
+
+        assert_eq!(results[0]["ruleId"], "nonliteral-format");
+        assert_eq!(results[0]["level"], "error");
+        let region = &results[0]["locations"][0]["physicalLocation"]["region"];
+        assert_eq!(region["startLine"], 2);
+        assert_eq!(region["startColumn"], 8);
+        assert_eq!(region["byteOffset"], 14);
+        assert_eq!(region["byteLength"], 1);
+
+        // not exploitable, so only a SARIF warning
+        assert_eq!(results[1]["ruleId"], "specifier-arg-mismatch");
+        assert_eq!(results[1]["level"], "warning");
+        assert_eq!(
+            results[1]["locations"][0]["physicalLocation"]["artifactLocation"]["uri"],
+            "main.c"
+        );
+        assert!(!results[1]["relatedLocations"]
+            .as_array()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn write_back_label_spans_the_specifier() {
+        for source in [r#"printf("x %n", &c);"#, r#"printf("x %1$hhn", &c);"#] {
+            let errors = IntermediateRepresentation::parse(source, &Config::default())
+                .expect_err("`%n` is invalid");
+            let labels: Vec<LabeledSpan> = errors[0]
+                .error()
+                .labels()
+                .expect("error has labels")
+                .collect();
+            let start = source.find('%').unwrap();
+            let end = source.find("n\"").unwrap() + 1;
+            assert_eq!(labels.len(), 1);
+            assert_eq!(
+                labels[0].offset()..labels[0].offset() + labels[0].len(),
+                start..end
+            );
+        }
+    }
+
+    #[test]
+    fn short_columns_count_characters() {
+        let source = "// ünïcödé\r\nx = 1; printf(\"é %d\");\r\n";
+        let mut diagnostics = Diagnostics::default();
+        let errors = IntermediateRepresentation::parse(source, &Config::default())
+            .expect_err("source code is invalid");
+        diagnostics.add("main.c".into(), source.to_string(), errors);
+        assert_eq!(
+            diagnostics.to_short(),
+            "main.c:2:15: error: Excess specifiers, this will read arbitrary data off the stack! [excess-specifiers]\n"
+        );
+    }
+
+    #[test]
+    fn summary_counts_repeated_rules() {
+        let config = Config {
+            check_buffers: true,
+            ..Config::default()
+        };
+        let main = "printf(\"%d\");\nprintf(\"%s\", x, y);\nprintf(\"%s %s\");\n";
+        let util = "printf(\"%d %d\");\nsnprintf(buf, 1, \"ab\");\n";
+        let mut diagnostics = Diagnostics::default();
+        for (filename, source) in [("main.c", main), ("util.c", util)] {
+            let errors = IntermediateRepresentation::parse(source, &config)
+                .expect_err("source code is invalid");
+            diagnostics.add(filename.into(), source.to_string(), errors);
+        }
+        assert_eq!(
+            diagnostics.summary(),
+            "3 errors: 3 excess-specifiers; 2 warnings: 1 buffer-too-small, 1 excess-args"
+        );
     }
 }
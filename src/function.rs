@@ -0,0 +1,193 @@
+use crate::config::Config;
+use crate::error::Error;
+use crate::lex::ArgToken;
+use crate::parse::Arg;
+
+/// Functions in the `printf` family that calls to are validated.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Function {
+    /// printf
+    Printf,
+    /// sprintf
+    Sprintf,
+    /// snprintf
+    Snprintf,
+    /// fprintf
+    Fprintf,
+    /// dprintf
+    Dprintf,
+    /// asprintf
+    Asprintf,
+    /// wprintf
+    Wprintf,
+}
+
+/// Arguments that come before the format string, e.g. the buffer of `sprintf`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PreArg {
+    /// A `char*` to write the formatted string to.
+    Buffer,
+    /// A `size_t` with the size of the buffer.
+    Size,
+    /// A `FILE*` to write the formatted string to.
+    Stream,
+    /// An `int` file descriptor to write the formatted string to.
+    Fd,
+    /// A `char**` to store the allocated formatted string in.
+    Out,
+}
+
+impl Function {
+    /// Returns the function with a name, if it's one that's validated.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "printf" => Some(Function::Printf),
+            "sprintf" => Some(Function::Sprintf),
+            "snprintf" => Some(Function::Snprintf),
+            "fprintf" => Some(Function::Fprintf),
+            "dprintf" => Some(Function::Dprintf),
+            "asprintf" => Some(Function::Asprintf),
+            "wprintf" => Some(Function::Wprintf),
+            // add other print kinds here
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the function, e.g. `printf`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Function::Printf => "printf",
+            Function::Sprintf => "sprintf",
+            Function::Snprintf => "snprintf",
+            Function::Fprintf => "fprintf",
+            Function::Dprintf => "dprintf",
+            Function::Asprintf => "asprintf",
+            Function::Wprintf => "wprintf",
+        }
+    }
+
+    /// Returns the arguments that come before the format string, in order.
+    pub fn pre_args(self) -> &'static [PreArg] {
+        match self {
+            Function::Printf => &[],
+            Function::Sprintf => &[PreArg::Buffer],
+            Function::Snprintf => &[PreArg::Buffer, PreArg::Size],
+            Function::Fprintf => &[PreArg::Stream],
+            Function::Dprintf => &[PreArg::Fd],
+            Function::Asprintf => &[PreArg::Out],
+            Function::Wprintf => &[],
+        }
+    }
+}
+
+impl PreArg {
+    /// Returns an error if pre-args are checked and `arg`, whose source code
+    /// is `arg_source`, is cast to or obviously has a type that this pre-arg
+    /// can't be, e.g. a buffer of `5` or `"text"`.
+    pub fn check(self, arg: &Arg<'_>, arg_source: &str, config: &Config) -> Option<Error> {
+        if !config.check_buffers {
+            return None;
+        }
+
+        let ctype = match &arg.cast {
+            Some((ctype, _)) => *ctype,
+            None => arg.inferred_ctype(arg_source, config)?,
+        };
+        let span = arg.span.clone();
+
+        match self {
+            PreArg::Buffer if !ctype.is_pointer() => Some(Error::NonPointerBuffer { span, ctype }),
+            // string literals are pointers, but writing to them is undefined behavior
+            PreArg::Buffer
+                if matches!(
+                    arg.single_token,
+                    Some(ArgToken::String(_) | ArgToken::RawString(_))
+                ) =>
+            {
+                Some(Error::LiteralBuffer(span))
+            }
+            PreArg::Out if !ctype.is_pointer() => Some(Error::NonPointerOut { span, ctype }),
+            PreArg::Size if !ctype.is_integer() => Some(Error::NonIntegerSize { span, ctype }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lex::SourceToken;
+    use crate::parse::Args;
+    use logos::Logos;
+
+    /// Returns the error from checking `arg` as a pre-arg, with pre-args
+    /// checked.
+    fn check(pre_arg: PreArg, arg: &str) -> Option<Error> {
+        let config = Config {
+            check_buffers: true,
+            ..Config::default()
+        };
+        let source = format!("f({arg})");
+        let mut lex = SourceToken::lexer(&source);
+        lex.by_ref().take(2).for_each(drop);
+        let arg = Args::new(&mut lex, &config).next().expect("one argument");
+        pre_arg.check(&arg, &source[arg.span.clone()], &config)
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for function in [Function::Printf, Function::Sprintf, Function::Snprintf] {
+            assert_eq!(Function::from_name(function.name()), Some(function));
+        }
+        assert_eq!(Function::from_name("vprintf"), None);
+    }
+
+    #[test]
+    fn wrong_typed_pre_args() {
+        assert!(matches!(
+            check(PreArg::Buffer, "5"),
+            Some(Error::NonPointerBuffer { .. })
+        ));
+        assert!(matches!(
+            check(PreArg::Buffer, "(float) p"),
+            Some(Error::NonPointerBuffer { .. })
+        ));
+        assert!(check(PreArg::Buffer, "(char*) p").is_none());
+        assert!(check(PreArg::Buffer, "&buf[1]").is_none());
+
+        assert!(matches!(
+            check(PreArg::Size, "2.5"),
+            Some(Error::NonIntegerSize { .. })
+        ));
+        assert!(matches!(
+            check(PreArg::Size, "(char*) n"),
+            Some(Error::NonIntegerSize { .. })
+        ));
+        assert!(check(PreArg::Size, "sizeof buf").is_none());
+        assert!(check(PreArg::Size, "(short) n").is_none());
+    }
+
+    #[test]
+    fn unchecked_without_check_buffers() {
+        let source = "f(5)";
+        let mut lex = SourceToken::lexer(source);
+        lex.by_ref().take(2).for_each(drop);
+        let config = Config::default();
+        let arg = Args::new(&mut lex, &config).next().unwrap();
+        assert!(PreArg::Buffer.check(&arg, source, &config).is_none());
+    }
+
+    #[test]
+    fn literal_buffers() {
+        assert!(matches!(
+            check(PreArg::Buffer, "\"out\""),
+            Some(Error::LiteralBuffer(_))
+        ));
+        // the cast doesn't make it writable
+        assert!(matches!(
+            check(PreArg::Buffer, "(char*) \"out\""),
+            Some(Error::LiteralBuffer(_))
+        ));
+        assert!(check(PreArg::Buffer, "buf").is_none());
+    }
+}
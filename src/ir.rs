@@ -1,156 +1,255 @@
-use crate::error::Error;
-use crate::lex::SourceToken;
-use crate::parse::{Args, Specifier, Specifiers};
+use crate::config::Config;
+use crate::error::{Error, Reported};
+use crate::function::Function;
+use crate::lex::{self, SourceToken};
+use crate::location::{LineIndex, Location};
+use crate::macros::{self, Macros};
+use crate::parse::{self, Arg, Args, Specifier, Specifiers};
 use displaydoc::Display;
 use logos::{Lexer, Logos};
-use std::fmt;
+use std::fmt::{self, Write};
+use std::mem;
 use std::ops::Range;
+use std::slice;
+use std::str::FromStr;
 
 /// Intermediate representation for a parsed C file.
 #[derive(Debug)]
-pub struct IntermediateRepresentation<'src>(Interpolation<'src, Site<'src>>);
+pub struct IntermediateRepresentation<'src> {
+    interpolation: Interpolation<'src, Site<'src>>,
+    /// Warnings, which don't stop the source code from being valid.
+    warnings: Vec<Reported>,
+    /// Macros that were resolved while parsing, e.g. `MAXLEN` in `#define MAXLEN 64`.
+    deps: Vec<(&'src str, &'src str)>,
+}
 
 impl<'src> IntermediateRepresentation<'src> {
     /// Parse C source code into an [`IntermediateRepresentation`],
-    /// otherwise return a list of [`Error`]s.
-    pub fn parse(source: &'src str) -> Result<Self, Vec<Error>> {
+    /// otherwise return a list of [`Error`]s tagged with the severity
+    /// that `config.rules` reports them at.
+    ///
+    /// Calls with only warnings or advice are still valid, and those are
+    /// kept in [`warnings`](Self::warnings) instead.
+    pub fn parse(source: &'src str, config: &Config) -> Result<Self, Vec<Reported>> {
         let mut lex = SourceToken::lexer(source);
-        let mut span: Option<Range<usize>> = None;
+        let macros = Macros::collect(source);
+        let line_index = LineIndex::new(source);
+        // start of the source code that hasn't been put in a chunk yet
+        let mut chunk_start = 0;
         let mut pairs = Some(Vec::with_capacity(0));
         let mut errors = Vec::with_capacity(0);
 
-        while let Some(token) = lex.next() {
-            let (before, site) = match token {
-                SourceToken::Identifier("printf") => {
-                    let before = span
-                        .as_ref()
-                        .map(|span| &source[span.start..lex.span().start])
-                        .unwrap_or("");
-
-                    if lex.next() != Some(SourceToken::LParen) {
-                        continue;
-                    }
+        while let Some((function, name_span)) = next_call(&mut lex) {
+            let before = &source[chunk_start..name_span.start];
+            let location = line_index.location(name_span.start);
 
-                    span = None;
+            let call_errors = errors.len();
+            let call = parse_args(&mut lex, function, config, &macros, &mut errors);
+            let site = call.map(|call| {
+                let Call { pre_args, format } = call;
 
-                    let printf = parse_args(&mut lex, &mut errors)
-                        .map(|([], format)| Site::Printf { format });
+                match (function, &pre_args[..]) {
+                    (Function::Printf, []) => Site::Printf { location, format },
+                    (Function::Sprintf, [buffer]) => Site::Sprintf {
+                        location,
+                        buffer: &source[buffer.span.clone()],
+                        format,
+                    },
+                    (Function::Snprintf, [buffer, bufsz]) => {
+                        let bufsz_source = &source[bufsz.span.clone()];
+                        // a warning, so the call is still rewritten
+                        errors.extend(buffer_too_small(
+                            bufsz_source,
+                            bufsz.span.clone(),
+                            &format,
+                            &macros,
+                        ));
 
-                    (before, printf)
-                }
-                SourceToken::Identifier("sprintf") => {
-                    let before = span
-                        .take()
-                        .map(|span| &source[span.start..lex.span().start])
-                        .unwrap_or("");
-
-                    if lex.next() != Some(SourceToken::LParen) {
-                        continue;
+                        Site::Snprintf {
+                            location,
+                            buffer: &source[buffer.span.clone()],
+                            bufsz: bufsz_source,
+                            format,
+                        }
                     }
-
-                    span = None;
-
-                    let sprintf = parse_args(&mut lex, &mut errors)
-                        .map(|([buffer], format)| Site::Sprintf { buffer, format });
-
-                    (before, sprintf)
+                    (Function::Fprintf, [stream]) => Site::Fprintf {
+                        location,
+                        stream: &source[stream.span.clone()],
+                        format,
+                    },
+                    (Function::Dprintf, [fd]) => Site::Dprintf {
+                        location,
+                        fd: &source[fd.span.clone()],
+                        format,
+                    },
+                    (Function::Asprintf, [out]) => Site::Asprintf {
+                        location,
+                        out: &source[out.span.clone()],
+                        format,
+                    },
+                    (Function::Wprintf, []) => Site::Wprintf { location, format },
+                    _ => unreachable!("`parse_args` parses exactly `function.pre_args()`"),
                 }
-                SourceToken::Identifier("snprintf") => {
-                    let before = span
-                        .take()
-                        .map(|span| &source[span.start..lex.span().start])
-                        .unwrap_or("");
-
-                    if lex.next() != Some(SourceToken::LParen) {
-                        continue;
-                    }
-
-                    span = None;
-
-                    let snprintf =
-                        parse_args(&mut lex, &mut errors).map(|([buffer, bufsz], format)| {
-                            Site::Snprintf {
-                                buffer,
-                                bufsz,
-                                format,
-                            }
-                        });
+            });
 
-                    (before, snprintf)
+            let site = site.filter(|site| {
+                let len = errors.len();
+                for lint in &config.lints {
+                    errors.extend(lint.check(site, source));
                 }
-                // add other print kinds here
-                _ => {
-                    span = Some(match span {
-                        Some(Range { start, .. }) => start..lex.span().end,
-                        None => lex.span(),
-                    });
-                    continue;
-                }
-            };
+                !config.rules.any_fatal(&errors[len..])
+            });
 
             match (&mut pairs, site) {
                 (Some(pairs), Some(site)) => {
                     pairs.push((before, site));
                 }
+                // a call that can't be rewritten, but that isn't invalid
+                // either, is left as is in the chunk after it
+                (_, None) if !config.rules.any_fatal(&errors[call_errors..]) => continue,
                 (_, None) => pairs = None,
                 _ => { /* ignore */ }
             }
+
+            // the lexer was moved to the end of the call
+            chunk_start = lex.span().end;
         }
 
+        // errors within a call are found in several passes
+        errors.sort_by_key(Error::offset);
+        let errors = config.rules.apply(errors);
         match pairs {
-            Some(pairs) => Ok(Self(Interpolation::new(
-                pairs,
-                span.take().map(|span| &lex.source()[span]).unwrap_or(""),
-            ))),
-            None => Err(errors),
+            Some(pairs) if !errors.iter().any(Reported::is_error) => Ok(Self {
+                interpolation: Interpolation::new(pairs, &source[chunk_start..]),
+                warnings: errors,
+                deps: macros.into_used(),
+            }),
+            _ => Err(errors),
+        }
+    }
+
+    /// Returns the warnings and advice reported while parsing, in the order
+    /// they appear, e.g. redundant casts with
+    /// [`Config::warn_redundant_casts`](crate::config::Config::warn_redundant_casts).
+    pub fn warnings(&self) -> &[Reported] {
+        &self.warnings
+    }
+
+    /// Returns the warnings and advice reported while parsing, taking them
+    /// out of the parsed source code.
+    pub fn into_warnings(self) -> Vec<Reported> {
+        self.warnings
+    }
+
+    /// Returns the calls to `printf` and family, in the order they appear.
+    pub fn sites(&self) -> impl Iterator<Item = &Site<'src>> {
+        self.interpolation.pairs.iter().map(|(_, site)| site)
+    }
+
+    /// Returns the names and values of the macros that were resolved while
+    /// parsing, sorted by name.
+    pub fn deps(&self) -> &[(&'src str, &'src str)] {
+        &self.deps
+    }
+
+    /// Returns counts of what the `display_*` rewrites change.
+    pub fn stats(&self) -> TransformStats {
+        let mut stats = TransformStats::default();
+        for site in self.sites() {
+            stats.sites += 1;
+            for call_arg in site.format().args.iter() {
+                match call_arg.cast {
+                    Some(_) => stats.casts_added += 1,
+                    None => stats.args_unchanged += 1,
+                }
+            }
         }
+        stats
+    }
+
+    /// Returns [`Error::UnoptimizableStar`] for each `*` width or precision,
+    /// which [`display_optimize`](Self::display_optimize) can't pass on.
+    pub fn unoptimizable(&self) -> Vec<Error> {
+        self.sites()
+            .flat_map(|site| site.format().interpolation.pairs.iter())
+            .flat_map(|(_, value)| value.stars.iter().cloned())
+            .map(Error::UnoptimizableStar)
+            .collect()
     }
 
     /// Returns a displayable version of [`IntermediateRepresentation`] that
     /// replaces `printf` and family with optimized calls.
+    ///
+    /// Optimized calls don't take `*` widths and precisions, so check
+    /// [`unoptimizable`](Self::unoptimizable) first.
     pub fn display_optimize(&self) -> impl fmt::Display + '_ {
         DisplayIntermediateRepresentation {
-            interpolation: &self.0,
+            interpolation: &self.interpolation,
             format_site: |site: &Site, f: &mut fmt::Formatter<'_>| -> fmt::Result {
                 let format = match site {
-                    Site::Printf { format } => {
+                    Site::Printf { format, .. } => {
                         f.write_str("safe_printf(")?;
                         format
                     }
-                    Site::Sprintf { buffer, format } => {
-                        write!(f, "safe_sprintf((char* restrict) ({buffer}), ")?;
+                    Site::Sprintf { buffer, format, .. } => {
+                        write!(f, "safe_sprintf({}, ", Cast::new("char* restrict", buffer))?;
                         format
                     }
                     Site::Snprintf {
                         buffer,
                         bufsz,
                         format,
+                        ..
                     } => {
                         write!(
                             f,
-                            "safe_snprintf((char* restrict) ({buffer}), (size_t) ({bufsz}), "
+                            "safe_snprintf({}, {}, ",
+                            Cast::new("char* restrict", buffer),
+                            Cast::new("size_t", bufsz)
                         )?;
                         format
                     }
+                    Site::Fprintf { stream, format, .. } => {
+                        write!(f, "safe_fprintf({}, ", Cast::new("FILE*", stream))?;
+                        format
+                    }
+                    Site::Dprintf { fd, format, .. } => {
+                        write!(f, "safe_dprintf({}, ", Cast::new("int", fd))?;
+                        format
+                    }
+                    Site::Asprintf { out, format, .. } => {
+                        write!(f, "safe_asprintf({}, ", Cast::new("char**", out))?;
+                        format
+                    }
+                    Site::Wprintf { format, .. } => {
+                        f.write_str("safe_wprintf(")?;
+                        format
+                    }
                 };
 
-                write!(f, "{}", format.pairs.len() * 3 + 1)?;
+                write!(f, "{}", format.interpolation.pairs.len() * 3 + 1)?;
 
-                for (chunk, displayable) in format.pairs.iter() {
+                for (chunk, displayable) in format.interpolation.pairs.iter() {
+                    // char literals like `'x'` don't have an address, so `%c`
+                    // arguments are copied into a compound literal instead
+                    let specifier = &displayable.specifier;
+                    let (open, close) = match specifier.ctype {
+                        // `h` and `hh` values are formatted from the `int` they're promoted to
+                        _ if specifier.is_promoted() => ("&(int){", "}"),
+                        CType::Char => ("&(char){", "}"),
+                        ctype if !ctype.is_pointer() => ("&(", ")"),
+                        _ => ("(", ")"),
+                    };
                     write!(
                         f,
-                        ", \"{chunk}\", (void*) {}({}), {}",
-                        if displayable.specifier.ctype != CType::String {
-                            "&"
-                        } else {
-                            ""
-                        },
+                        ", {}, (void*) {open}{}{close}, {}",
+                        format.literal(chunk),
                         displayable.arg,
-                        displayable.specifier.ctype.format_fn()
+                        displayable.specifier.format_fn()
                     )?;
                 }
 
-                write!(f, ", \"{}\")", format.last)
+                write!(f, ", {})", format.literal(format.interpolation.last))
             },
         }
     }
@@ -159,53 +258,132 @@ impl<'src> IntermediateRepresentation<'src> {
     /// adds type casts to all function arguments..
     pub fn display_typecast(&self) -> impl fmt::Display + '_ {
         DisplayIntermediateRepresentation {
-            interpolation: &self.0,
+            interpolation: &self.interpolation,
             format_site: |site: &Site, f: &mut fmt::Formatter<'_>| -> fmt::Result {
                 let format = match site {
-                    Site::Printf { format } => {
-                        f.write_str("printf(\"")?;
+                    Site::Printf { format, .. } => {
+                        f.write_str("printf(")?;
                         format
                     }
-                    Site::Sprintf { buffer, format } => {
-                        write!(f, "sprintf((char* restrict) ({buffer}), \"")?;
+                    Site::Sprintf { buffer, format, .. } => {
+                        write!(f, "sprintf({}, ", Cast::new("char* restrict", buffer))?;
                         format
                     }
                     Site::Snprintf {
                         buffer,
                         bufsz,
                         format,
+                        ..
                     } => {
                         write!(
                             f,
-                            "snprintf((char* restrict) ({buffer}), (size_t) ({bufsz}), \""
+                            "snprintf({}, {}, ",
+                            Cast::new("char* restrict", buffer),
+                            Cast::new("size_t", bufsz)
                         )?;
                         format
                     }
+                    Site::Fprintf { stream, format, .. } => {
+                        write!(f, "fprintf({}, ", Cast::new("FILE*", stream))?;
+                        format
+                    }
+                    Site::Dprintf { fd, format, .. } => {
+                        write!(f, "dprintf({}, ", Cast::new("int", fd))?;
+                        format
+                    }
+                    Site::Asprintf { out, format, .. } => {
+                        write!(f, "asprintf({}, ", Cast::new("char**", out))?;
+                        format
+                    }
+                    Site::Wprintf { format, .. } => {
+                        f.write_str("wprintf(")?;
+                        format
+                    }
                 };
 
-                // reconstruct the format string
-                for (chunk, FormatValue { specifier, .. }) in format.pairs.iter() {
-                    f.write_str(chunk)?;
-                    write!(
-                        f,
-                        "%{}{}",
-                        specifier.options,
-                        specifier.ctype.specifier_char()
-                    )?;
-                }
-                write!(f, "{}\"", format.last)?;
+                format.write_source(f)?;
 
                 // reconstruct the arguments, but with type casts now
-                for (_, displayable) in format.pairs.iter() {
-                    if displayable.type_checked {
-                        write!(f, ", {}", displayable.arg)?;
-                    } else {
+                for CallArg { arg, cast } in format.args.iter() {
+                    match cast {
+                        Some(ctype) => write!(f, ", ({ctype}) ({arg})")?,
+                        None => write!(f, ", {arg}")?,
+                    }
+                }
+
+                f.write_str(")")
+            },
+        }
+    }
+
+    /// Returns a displayable version of [`IntermediateRepresentation`] that
+    /// rewrites deprecated specifiers to their modern equivalents and puts
+    /// flags in a canonical order, leaving the arguments unchanged.
+    pub fn display_modernize(&self) -> impl fmt::Display + '_ {
+        DisplayIntermediateRepresentation {
+            interpolation: &self.interpolation,
+            format_site: |site: &Site, f: &mut fmt::Formatter<'_>| -> fmt::Result {
+                let format = match site {
+                    Site::Printf { format, .. } => {
+                        f.write_str("printf(")?;
+                        format
+                    }
+                    Site::Sprintf { buffer, format, .. } => {
+                        write!(f, "sprintf({buffer}, ")?;
+                        format
+                    }
+                    Site::Snprintf {
+                        buffer,
+                        bufsz,
+                        format,
+                        ..
+                    } => {
+                        write!(f, "snprintf({buffer}, {bufsz}, ")?;
+                        format
+                    }
+                    Site::Fprintf { stream, format, .. } => {
+                        write!(f, "fprintf({stream}, ")?;
+                        format
+                    }
+                    Site::Dprintf { fd, format, .. } => {
+                        write!(f, "dprintf({fd}, ")?;
+                        format
+                    }
+                    Site::Asprintf { out, format, .. } => {
+                        write!(f, "asprintf({out}, ")?;
+                        format
+                    }
+                    Site::Wprintf { format, .. } => {
+                        f.write_str("wprintf(")?;
+                        format
+                    }
+                };
+
+                // reconstruct the format string with normalized specifiers, but
+                // a macro is left as is, since its `#define` isn't rewritten
+                if let Some(name) = format.macro_name {
+                    f.write_str(name)?;
+                } else {
+                    f.write_str(format.opening)?;
+                    for (chunk, FormatValue { specifier, .. }) in format.interpolation.pairs.iter()
+                    {
+                        f.write_str(chunk)?;
+                        f.write_char('%')?;
+                        if let Some(position) = specifier.position {
+                            write!(f, "{position}$")?;
+                        }
                         write!(
                             f,
-                            ", ({}) ({})",
-                            displayable.specifier.ctype, displayable.arg
+                            "{}{}",
+                            specifier.canonical_options(),
+                            specifier.modern_conversion()
                         )?;
                     }
+                    write!(f, "{}{}", format.interpolation.last, format.closing)?;
+                }
+
+                for CallArg { arg, .. } in format.args.iter() {
+                    write!(f, ", {arg}")?;
                 }
 
                 f.write_str(")")
@@ -233,36 +411,359 @@ where
     }
 }
 
+/// Counts of what rewriting an [`IntermediateRepresentation`] changes.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct TransformStats {
+    /// Calls to `printf` and family that were rewritten.
+    pub sites: usize,
+    /// Arguments that `--typecast` adds a cast to.
+    pub casts_added: usize,
+    /// Arguments that `--typecast` leaves as is, since they're already cast or
+    /// obviously have the right type.
+    pub args_unchanged: usize,
+}
+
+impl fmt::Display for TransformStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "rewrote {} call {}, added {} {}, left {} {} unchanged",
+            self.sites,
+            plural(self.sites, "site", "sites"),
+            self.casts_added,
+            plural(self.casts_added, "cast", "casts"),
+            self.args_unchanged,
+            plural(self.args_unchanged, "argument", "arguments"),
+        )
+    }
+}
+
+fn plural<'a>(count: usize, one: &'a str, many: &'a str) -> &'a str {
+    if count == 1 {
+        one
+    } else {
+        many
+    }
+}
+
 /// Different callsites for string formatting in C.
 #[derive(Debug)]
 pub enum Site<'src> {
     /// printf
     Printf {
-        format: Interpolation<'src, FormatValue<'src>>,
+        location: Location,
+        format: Format<'src>,
     },
     /// sprintf
     Sprintf {
+        location: Location,
         buffer: &'src str,
-        format: Interpolation<'src, FormatValue<'src>>,
+        format: Format<'src>,
     },
     /// snprintf
     Snprintf {
+        location: Location,
         buffer: &'src str,
         bufsz: &'src str,
-        format: Interpolation<'src, FormatValue<'src>>,
+        format: Format<'src>,
+    },
+    /// fprintf
+    Fprintf {
+        location: Location,
+        stream: &'src str,
+        format: Format<'src>,
+    },
+    /// dprintf
+    Dprintf {
+        location: Location,
+        fd: &'src str,
+        format: Format<'src>,
+    },
+    /// asprintf
+    Asprintf {
+        location: Location,
+        out: &'src str,
+        format: Format<'src>,
+    },
+    /// wprintf
+    Wprintf {
+        location: Location,
+        format: Format<'src>,
     },
 }
 
+impl<'src> Site<'src> {
+    /// Returns the location of the function name of the call.
+    pub fn location(&self) -> Location {
+        match self {
+            Site::Printf { location, .. } => *location,
+            Site::Sprintf { location, .. } => *location,
+            Site::Snprintf { location, .. } => *location,
+            Site::Fprintf { location, .. } => *location,
+            Site::Dprintf { location, .. } => *location,
+            Site::Asprintf { location, .. } => *location,
+            Site::Wprintf { location, .. } => *location,
+        }
+    }
+
+    /// Returns the format string of the call.
+    pub fn format(&self) -> &Format<'src> {
+        match self {
+            Site::Printf { format, .. } => format,
+            Site::Sprintf { format, .. } => format,
+            Site::Snprintf { format, .. } => format,
+            Site::Fprintf { format, .. } => format,
+            Site::Dprintf { format, .. } => format,
+            Site::Asprintf { format, .. } => format,
+            Site::Wprintf { format, .. } => format,
+        }
+    }
+
+    /// Returns the call as written, but on a single line with whitespace
+    /// outside of literals collapsed, e.g. `printf("%d\n", x + 1)`.
+    pub fn oneline(&self) -> String {
+        let (name, pre_args): (_, &[&str]) = match self {
+            Site::Printf { .. } => ("printf", &[]),
+            Site::Sprintf { buffer, .. } => ("sprintf", slice::from_ref(buffer)),
+            Site::Snprintf { buffer, bufsz, .. } => ("snprintf", &[*buffer, *bufsz][..]),
+            Site::Fprintf { stream, .. } => ("fprintf", slice::from_ref(stream)),
+            Site::Dprintf { fd, .. } => ("dprintf", slice::from_ref(fd)),
+            Site::Asprintf { out, .. } => ("asprintf", slice::from_ref(out)),
+            Site::Wprintf { .. } => ("wprintf", &[]),
+        };
+        let format = self.format();
+
+        let mut call = format!("{name}(");
+        for pre_arg in pre_args {
+            write!(call, "{pre_arg}, ").expect("writing to a string can't fail");
+        }
+        format
+            .write_source(&mut call)
+            .expect("writing to a string can't fail");
+        for CallArg { arg, .. } in format.args.iter() {
+            write!(call, ", {arg}").expect("writing to a string can't fail");
+        }
+        call.push(')');
+
+        collapse_whitespace(&call)
+    }
+}
+
+/// A format string in a call, and the arguments it interpolates.
+#[derive(Debug)]
+pub struct Format<'src> {
+    /// Source code of the format string, between the quotes.
+    source: &'src str,
+    /// Span of the format string, including the quotes.
+    span: Range<usize>,
+    /// Text before the contents, e.g. `"` or `R"x(` for a raw string literal.
+    opening: &'src str,
+    /// Text after the contents, e.g. `"` or `)x"` for a raw string literal.
+    closing: &'src str,
+    /// Name of the macro the format string was passed as, e.g. `FMT` after
+    /// `#define FMT "%d\n"`, in which case the span is in the `#define`.
+    macro_name: Option<&'src str>,
+    /// The format string, split up by the arguments it interpolates.
+    interpolation: Interpolation<'src, FormatValue<'src>>,
+    /// Every argument after the format string, in the order they're passed.
+    args: Vec<CallArg<'src>>,
+}
+
+impl<'src> Format<'src> {
+    /// Returns the format string exactly as written, between the quotes.
+    pub fn source(&self) -> &'src str {
+        self.source
+    }
+
+    /// Returns the span of the format string, including the quotes.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    /// Returns the specifiers in the format string, each with the source
+    /// code of the argument it formats.
+    pub fn specifiers(&self) -> impl Iterator<Item = (&Specifier<'src>, &'src str)> {
+        self.interpolation
+            .pairs
+            .iter()
+            .map(|(_, value)| (&value.specifier, value.arg))
+    }
+}
+
+impl<'src> Format<'src> {
+    /// Writes the format string as it was passed, which is the name of the
+    /// macro if it was defined with one.
+    fn write_source(&self, f: &mut impl Write) -> fmt::Result {
+        match self.macro_name {
+            Some(name) => f.write_str(name),
+            None => write!(f, "{}{}{}", self.opening, self.source, self.closing),
+        }
+    }
+
+    /// Returns a displayable string literal of the same kind as the format
+    /// string, containing `text` from it.
+    fn literal<'a>(&'a self, text: &'a str) -> Literal<'a> {
+        Literal { format: self, text }
+    }
+}
+
+/// Replaces each run of whitespace outside of string and character literals
+/// with a single space.
+fn collapse_whitespace(code: &str) -> String {
+    let mut collapsed = String::with_capacity(code.len());
+    let mut quote = None;
+    let mut chars = code.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(_), '\\') => {
+                collapsed.push(c);
+                collapsed.extend(chars.next());
+                continue;
+            }
+            (Some(q), _) if c == q => quote = None,
+            (None, _) if c.is_whitespace() => {
+                if !collapsed.ends_with(' ') {
+                    collapsed.push(' ');
+                }
+                continue;
+            }
+            _ => {}
+        }
+        collapsed.push(c);
+    }
+    collapsed
+}
+
+/// A string literal containing text from a format string, with ASCII control
+/// characters escaped, e.g. a tab becomes `\t`, and `%%` unescaped.
+struct Literal<'a> {
+    format: &'a Format<'a>,
+    text: &'a str,
+}
+
+impl fmt::Display for Literal<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.format.opening)?;
+
+        // the text is printed as is, so `%%` becomes a single `%`
+        let text = self.text.replace("%%", "%");
+
+        // raw string literals can contain anything but their closing delimiter
+        if self.format.opening.ends_with('(') {
+            f.write_str(&text)?;
+            return f.write_str(self.format.closing);
+        }
+
+        // the text can span concatenated literals, e.g. `a"  "b`, and only
+        // what's inside the quotes is escaped
+        let mut inside = true;
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => inside = !inside,
+                '\\' if inside => {
+                    f.write_char(c)?;
+                    if let Some(escaped) = chars.next() {
+                        f.write_char(escaped)?;
+                    }
+                    continue;
+                }
+                // other characters are kept as UTF-8, which an octal escape
+                // of the code point would mangle
+                _ if inside && c.is_ascii_control() => {
+                    match c {
+                        '\n' => f.write_str("\\n")?,
+                        '\t' => f.write_str("\\t")?,
+                        '\r' => f.write_str("\\r")?,
+                        '\x0b' => f.write_str("\\v")?,
+                        '\x0c' => f.write_str("\\f")?,
+                        _ => write!(f, "\\{:03o}", c as u32)?,
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            f.write_char(c)?;
+        }
+
+        f.write_str(self.format.closing)
+    }
+}
+
+/// An argument before the format string with a type cast, e.g. `(int) (fd)`.
+///
+/// Arguments that are already casted like that, e.g. by an earlier
+/// `--typecast`, are written as is.
+struct Cast<'a> {
+    ctype: &'static str,
+    arg: &'a str,
+}
+
+impl<'a> Cast<'a> {
+    fn new(ctype: &'static str, arg: &'a str) -> Self {
+        Self { ctype, arg }
+    }
+}
+
+impl fmt::Display for Cast<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let casted = self
+            .arg
+            .strip_prefix('(')
+            .and_then(|arg| arg.strip_prefix(self.ctype))
+            .and_then(|arg| arg.strip_prefix(')'))
+            .is_some_and(|operand| is_parenthesized(operand.trim_start()));
+
+        match casted {
+            true => f.write_str(self.arg),
+            false => write!(f, "({}) ({})", self.ctype, self.arg),
+        }
+    }
+}
+
+/// Returns `true` if code is wrapped in a single pair of parentheses, like
+/// `(a + b)` but not `(a) + (b)`.
+fn is_parenthesized(code: &str) -> bool {
+    if !code.starts_with('(') {
+        return false;
+    }
+
+    let mut depth = 0;
+    for (i, c) in code.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {}
+        }
+        if depth == 0 {
+            return i == code.len() - 1;
+        }
+    }
+    false
+}
+
 /// Pair between an argument to be printed and the specifier that tells us
 /// how it should be printed.
 #[derive(Debug)]
 pub struct FormatValue<'src> {
     /// The argument e.g. `name`.
     arg: &'src str,
-    /// The argument was type casted the same type as the specifier expects.
-    type_checked: bool,
     /// The specifier e.g. `%10s`.
     specifier: Specifier<'src>,
+    /// Spans of the `*` widths and precisions in the specifier, whose
+    /// arguments are passed too, e.g. `width` in `printf("%*d", width, x)`.
+    stars: Vec<Range<usize>>,
+}
+
+/// An argument after the format string, as it's passed to the call.
+#[derive(Debug)]
+struct CallArg<'src> {
+    /// The argument e.g. `name`.
+    arg: &'src str,
+    /// Type that `--typecast` casts the argument to, which is `None` if it's
+    /// already cast or obviously has the right type.
+    cast: Option<CType>,
 }
 
 /// C types that can be formatted.
@@ -270,28 +771,170 @@ pub struct FormatValue<'src> {
 pub enum CType {
     /// int
     Int,
+    /// long
+    Long,
     /// float
     Float,
     /// char*
     String,
+    /// wint_t
+    WideChar,
+    /// wchar_t*
+    WideString,
+    /// char
+    Char,
+    /// short
+    Short,
+    /// double
+    Double,
+    /// void*
+    Pointer,
+    /// unsigned
+    Unsigned,
+    /// long long
+    LongLong,
+    /// intmax_t
+    IntMax,
+    /// size_t
+    Size,
+    /// ptrdiff_t
+    PtrDiff,
+    /// unsigned long
+    UnsignedLong,
+    /// unsigned long long
+    UnsignedLongLong,
+    /// long double
+    LongDouble,
+}
+
+impl FromStr for CType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "int" => Ok(CType::Int),
+            "long" => Ok(CType::Long),
+            "float" => Ok(CType::Float),
+            "char*" => Ok(CType::String),
+            "wint_t" => Ok(CType::WideChar),
+            "wchar_t*" => Ok(CType::WideString),
+            "char" => Ok(CType::Char),
+            "short" => Ok(CType::Short),
+            "double" => Ok(CType::Double),
+            "void*" => Ok(CType::Pointer),
+            "unsigned" | "unsigned int" => Ok(CType::Unsigned),
+            "long long" => Ok(CType::LongLong),
+            "intmax_t" => Ok(CType::IntMax),
+            "size_t" => Ok(CType::Size),
+            "ptrdiff_t" => Ok(CType::PtrDiff),
+            "unsigned long" | "unsigned long int" => Ok(CType::UnsignedLong),
+            "unsigned long long" | "unsigned long long int" => Ok(CType::UnsignedLongLong),
+            "long double" => Ok(CType::LongDouble),
+            _ => Err(format!(
+                "unknown C type `{s}`, expected one of `int`, `long`, `float`, `char*`, `wint_t`, `wchar_t*`, `char`, `short`, `double`, `void*`, `unsigned`, `long long`, `intmax_t`, `size_t`, `ptrdiff_t`, `long double`, `unsigned long`, `unsigned long long`"
+            )),
+        }
+    }
 }
 
 impl CType {
-    /// Character used that tells C how to format a value in a format string.
-    pub fn specifier_char(&self) -> char {
+    /// Returns the type that a value of this type is passed as through
+    /// `...`, e.g. `int` for `char` and `float` for `double`, since `%f`
+    /// formats both.
+    pub fn promoted(self) -> CType {
+        match self {
+            CType::Char | CType::Short => CType::Int,
+            CType::Double => CType::Float,
+            ctype => ctype,
+        }
+    }
+
+    /// Returns `true` if a value of this type can be passed for a specifier
+    /// expecting `specifier`, e.g. `char` for `%d` or `char*` for `%p`.
+    ///
+    /// Only `char` can be passed for `%c`, even though it's promoted to `int`.
+    pub fn formats_as(self, specifier: CType) -> bool {
+        self == specifier
+            || self.promoted() == specifier
+            || (specifier == CType::Pointer && self.is_pointer())
+    }
+
+    /// Returns the unsigned type of the same size, e.g. `unsigned long` for
+    /// `long`, which is the type itself if it has no unsigned counterpart.
+    pub fn unsigned(self) -> CType {
+        match self {
+            CType::Int => CType::Unsigned,
+            CType::Long => CType::UnsignedLong,
+            CType::LongLong => CType::UnsignedLongLong,
+            ctype => ctype,
+        }
+    }
+
+    /// Characters that tell C how to format a value in a format string.
+    pub fn specifier(&self) -> &'static str {
         match self {
-            CType::Int => 'd',
-            CType::Float => 'f',
-            CType::String => 's',
+            CType::Int | CType::Short => "d",
+            CType::Long => "ld",
+            CType::Char => "c",
+            CType::Float | CType::Double => "f",
+            CType::String => "s",
+            CType::WideChar => "lc",
+            CType::WideString => "ls",
+            CType::Pointer => "p",
+            CType::Unsigned => "u",
+            CType::LongLong => "lld",
+            CType::IntMax => "jd",
+            CType::Size => "zu",
+            CType::PtrDiff => "td",
+            CType::UnsignedLong => "lu",
+            CType::UnsignedLongLong => "llu",
+            CType::LongDouble => "Lf",
         }
     }
 
+    /// Returns `true` if values of the C type are integers, e.g. `long`.
+    pub fn is_integer(&self) -> bool {
+        matches!(
+            self,
+            CType::Int
+                | CType::Long
+                | CType::Char
+                | CType::Short
+                | CType::WideChar
+                | CType::Unsigned
+                | CType::LongLong
+                | CType::IntMax
+                | CType::Size
+                | CType::PtrDiff
+                | CType::UnsignedLong
+                | CType::UnsignedLongLong
+        )
+    }
+
+    /// Returns `true` if values of the C type are pointers, e.g. `char*`.
+    pub fn is_pointer(&self) -> bool {
+        matches!(self, CType::String | CType::WideString | CType::Pointer)
+    }
+
     /// Name of our function ptr that optimizes a print for a C type.
     pub fn format_fn(&self) -> &'static str {
         match self {
-            CType::Int => "fmt_int",
-            CType::Float => "fmt_float",
+            CType::Int | CType::Short => "fmt_int",
+            CType::Long => "fmt_long",
+            CType::Char => "fmt_char",
+            CType::Float | CType::Double => "fmt_float",
             CType::String => "fmt_string",
+            CType::WideChar => "fmt_wide_char",
+            CType::WideString => "fmt_wide_string",
+            CType::Pointer => "fmt_pointer",
+            CType::Unsigned => "fmt_uint",
+            CType::LongLong => "fmt_long_long",
+            CType::IntMax => "fmt_intmax",
+            CType::Size => "fmt_size",
+            CType::PtrDiff => "fmt_ptrdiff",
+            CType::UnsignedLong => "fmt_ulong",
+            CType::UnsignedLongLong => "fmt_ulong_long",
+            CType::LongDouble => "fmt_long_double",
         }
     }
 }
@@ -310,12 +953,21 @@ impl<'src, T> Interpolation<'src, T> {
     }
 }
 
+/// A call to a string interpolating function, as parsed by [`parse_args`].
+#[derive(Debug)]
+pub struct Call<'src> {
+    /// The arguments before the format string.
+    pub pre_args: Vec<Arg<'src>>,
+    /// The format string and the arguments it interpolates.
+    pub format: Format<'src>,
+}
+
 /// Parses the arguments of any call to a string interpolating function,
 /// otherwise pushes [`Error`]s to `errors` and returns `None`.
 ///
-/// This function is also generic over `PRE_ARGS`, which is the number of arguments
-/// to parse before the format string. For `printf`, this is 0, but for something
-/// like `snprintf`, this is 2.
+/// The arguments before the format string are parsed according to
+/// [`Function::pre_args`]. For `printf`, there are none, but for something
+/// like `snprintf`, there are 2.
 ///
 /// Note that even if errors occur and `None` is returned, the lexer will
 /// still be moved to the end of the call.
@@ -327,109 +979,2253 @@ impl<'src, T> Interpolation<'src, T> {
 /// //      ^                                               ^
 /// //      assumes lexer starts here                       lexer ends up here
 /// ```
-pub fn parse_args<'src, const PRE_ARGS: usize>(
+pub fn parse_args<'src>(
     lex: &mut Lexer<'src, SourceToken<'src>>,
+    function: Function,
+    config: &Config,
+    macros: &Macros<'src>,
+    errors: &mut Vec<Error>,
+) -> Option<Call<'src>> {
+    let mut args = Args::new(lex, config);
+    let len = errors.len();
+    let call = parse_call(&mut args, function, config, macros, errors);
+
+    if let Some(span) = args.unterminated() {
+        // any other errors are from arguments being cut off
+        errors.truncate(len);
+        errors.push(Error::UnterminatedCall(span));
+        return None;
+    }
+
+    if let Some(span) = args.excessive_nesting() {
+        errors.push(Error::ExcessiveNesting {
+            span,
+            max_nesting: config.max_nesting,
+        });
+        return None;
+    }
+
+    if let Some(span) = args.empty_argument() {
+        errors.push(Error::EmptyArgument(span));
+        return None;
+    }
+
+    call
+}
+
+/// Moves the lexer past the next call to `printf` or family, up to its
+/// opening parenthesis, returning the function and the span of its name.
+///
+/// Names that aren't called, like in `void* f = printf;`, and members like
+/// `log->printf(x)` are skipped.
+pub fn next_call<'src>(
+    lex: &mut Lexer<'src, SourceToken<'src>>,
+) -> Option<(Function, Range<usize>)> {
+    let mut member = false;
+    while let Some(token) = lex::next_uncommented(lex) {
+        let after_member = mem::replace(&mut member, token == SourceToken::Member);
+
+        let SourceToken::Identifier(name) = token else {
+            continue;
+        };
+        if after_member {
+            // a member like `log->printf`, not the function itself
+            continue;
+        }
+        let Some(function) = Function::from_name(name) else {
+            continue;
+        };
+        let name_span = lex.span();
+        if lex::next_uncommented(lex) == Some(SourceToken::LParen) {
+            return Some((function, name_span));
+        }
+    }
+    None
+}
+
+/// Parses the arguments of a call with [`parse_args`], but without checking
+/// how deeply they're nested.
+fn parse_call<'src>(
+    args: &mut Args<'_, 'src>,
+    function: Function,
+    config: &Config,
+    macros: &Macros<'src>,
     errors: &mut Vec<Error>,
-) -> Option<(
-    [&'src str; PRE_ARGS],
-    Interpolation<'src, FormatValue<'src>>,
-)> {
-    let mut args = Args::new(lex);
-
-    let mut pre_args = [""; PRE_ARGS];
-    for pre_arg in pre_args.iter_mut() {
+) -> Option<Call<'src>> {
+    let mut pre_args = Vec::with_capacity(function.pre_args().len());
+    for _ in function.pre_args() {
         let Some(arg) = args.next() else {
             errors.push(Error::MissingFunctionArgs(args.short_circuit().1));
             return None;
         };
-        *pre_arg = args.source(arg.span);
+        pre_args.push(arg);
     }
 
-    let (format, format_span) = args
-        .next_format_string()
-        .map_err(|error| errors.push(error))
-        .ok()?;
+    // checked before the format string, so they're reported even if it's invalid
+    let call_len = errors.len();
+    for (arg, pre_arg) in pre_args.iter().zip(function.pre_args()) {
+        errors.extend(pre_arg.check(arg, args.source(arg.span.clone()), config));
+    }
 
-    let mut specifiers = Specifiers::new(format);
-    let mut maybe_pairs = Some(Vec::with_capacity(4));
-
-    loop {
-        match (specifiers.next(), args.next()) {
-            (Some(specifier), Some(arg)) => {
-                match (&mut maybe_pairs, arg.cast) {
-                    (Some(pairs), Some((cast_ctype, cast_span))) => {
-                        if cast_ctype == specifier.ctype {
-                            // passed typeck
-                            pairs.push((
-                                specifiers.before,
-                                FormatValue {
-                                    arg: args.source(arg.span),
-                                    type_checked: true,
-                                    specifier,
-                                },
-                            ));
-                        } else {
-                            // was okay, but just failed typeck
-                            errors.push(Error::SpecifierCastMismatch {
-                                specifier_span: specifiers.span(format_span.start + 1),
-                                specifier_ctype: specifier.ctype,
-                                cast_span,
-                                cast_ctype,
-                            });
-                            maybe_pairs = None;
-                        }
-                    }
-                    (Some(pairs), None) => {
-                        // no type casting, skip typeck
-                        pairs.push((
-                            specifiers.before,
-                            FormatValue {
-                                arg: args.source(arg.span),
-                                type_checked: false,
-                                specifier,
-                            },
-                        ));
-                    }
-                    (None, Some((cast_ctype, cast_span))) => {
-                        // already errored, maybe we can find a typeck mismatch
-                        if cast_ctype != specifier.ctype {
-                            // found one
-                            errors.push(Error::SpecifierCastMismatch {
-                                specifier_span: specifiers.span(format_span.start + 1),
-                                specifier_ctype: specifier.ctype,
-                                cast_span,
-                                cast_ctype,
-                            });
-                        }
-                    }
-                    _ => { /* ignore  */ }
-                }
-            }
-            (Some(_), None) => {
-                // got a specifier but not an associated arg
-                errors.push(Error::ExcessSpecifiers {
-                    format_span,
-                    args_span: args.short_circuit().1,
-                    additional_specifiers: specifiers.count() + 1,
-                });
-                return None;
-            }
-            (None, Some(_)) => {
-                // got an arg but not an associated specifier
-                let (remaining, args_span) = args.short_circuit();
-                errors.push(Error::ExcessArgs {
-                    format_span,
-                    args_span,
-                    additional_args: remaining + 1,
-                });
-                return None;
-            }
-            (None, None) => {
-                return Some((
-                    pre_args,
-                    Interpolation::new(maybe_pairs?, specifiers.remainder),
-                ))
-            }
+    let (format, format_span, macro_name) = match args.next_format_string(macros) {
+        Ok(format) => format,
+        Err(error) => {
+            errors.push(error);
+            // still move the lexer to the end of the call
+            args.short_circuit();
+            return None;
         }
+    };
+    // without whitespace after the last literal, e.g. in `"%d" , x`
+    let format_span =
+        format_span.start..format_span.start + args.source(format_span.clone()).trim_end().len();
+
+    // start of the format string's contents, after the quote or raw string delimiter
+    let format_offset = args.offset(format);
+
+    // checked before pairing, so they're reported even if that fails, but
+    // raw strings don't have escapes
+    let opening = args.source(format_span.start..format_offset);
+    if !opening.ends_with('(') {
+        errors.extend(
+            parse::invalid_escapes(format).map(|(span, reason)| Error::InvalidEscape {
+                span: format_offset + span.start..format_offset + span.end,
+                reason,
+            }),
+        );
+    }
+
+    let len = errors.len();
+    errors.extend(parse::unknown_conversions(format, config).map(|span| {
+        Error::unknown_conversion(
+            &format[span.clone()],
+            format_offset + span.start..format_offset + span.end,
+        )
+    }));
+    errors.extend(parse::unsupported_specifiers(format, config).map(|span| {
+        Error::UnsupportedSpecifier(format_offset + span.start..format_offset + span.end)
+    }));
+    errors.extend(
+        parse::split_specifiers(format).map(|span| {
+            Error::SplitSpecifier(format_offset + span.start..format_offset + span.end)
+        }),
+    );
+    errors.extend(parse::write_backs(format).map(|span| {
+        Error::WriteBackSpecifier(format_offset + span.start..format_offset + span.end)
+    }));
+    if errors.len() > len {
+        // can't tell which arguments go with which specifiers anymore
+        args.short_circuit();
+        return None;
+    }
+
+    if let Some((positional_span, sequential_span)) = parse::mixed_positions(format) {
+        errors.push(Error::MixedPositions {
+            positional_span: format_offset + positional_span.start
+                ..format_offset + positional_span.end,
+            sequential_span: format_offset + sequential_span.start
+                ..format_offset + sequential_span.end,
+        });
+        // they can't be paired either way
+        args.short_circuit();
+        return None;
+    }
+
+    let (interpolation, call_args) = if Specifiers::new(format).any(|s| s.position.is_some()) {
+        pair_positional(args, format, format_span.clone(), function, config, errors)
+    } else {
+        pair_sequential(args, format, format_span.clone(), function, config, errors)
+    }?;
+    if config.rules.any_fatal(&errors[call_len..]) {
+        return None;
+    }
+
+    Some(Call {
+        pre_args,
+        format: Format {
+            source: format,
+            opening,
+            // without whitespace after the last literal, e.g. in `"%d" , x`
+            closing: args
+                .source(format_offset + format.len()..format_span.end)
+                .trim_end(),
+            span: format_span,
+            macro_name,
+            interpolation,
+            args: call_args,
+        },
+    })
+}
+
+/// Pairs the specifiers of a format string with the arguments after it, in
+/// order, returning them with every argument as it's passed.
+///
+/// Returns `None` if there are more specifiers than arguments. Arguments
+/// without a specifier are reported, but passed through as is.
+fn pair_sequential<'src>(
+    args: &mut Args<'_, 'src>,
+    format: &'src str,
+    format_span: Range<usize>,
+    function: Function,
+    config: &Config,
+    errors: &mut Vec<Error>,
+) -> Option<(Interpolation<'src, FormatValue<'src>>, Vec<CallArg<'src>>)> {
+    let format_offset = args.offset(format);
+    let mut specifiers = Specifiers::new(format);
+    let mut pairs = Vec::with_capacity(4);
+    let mut call_args = Vec::with_capacity(4);
+
+    while let Some(specifier) = specifiers.next() {
+        let specifier_span = specifiers.span(format_offset);
+        let options_start =
+            specifier_span.end - specifier.conversion.len() - specifier.options.len();
+
+        // `*` widths and precisions take an `int` argument each, before the value
+        let mut stars = Vec::new();
+        let mut missing_stars = 0;
+        for (star_offset, _) in specifier.star_positions() {
+            let Some(arg) = args.next() else {
+                missing_stars += 1;
+                continue;
+            };
+            let star_span = options_start + star_offset..options_start + star_offset + 1;
+            call_args.push(check_star(&arg, star_span.clone(), args, config, errors));
+            stars.push(star_span);
+        }
+
+        let Some(arg) = args.next() else {
+            // got a specifier but not an associated arg, or not all of its `*` args
+            let (additional_specifiers, missing_args) = specifiers
+                .by_ref()
+                .fold((1, missing_stars + 1), |(count, missing), specifier| {
+                    (count + 1, missing + specifier.star_args() + 1)
+                });
+            errors.push(Error::ExcessSpecifiers {
+                format_span,
+                args_span: args.short_circuit().1,
+                additional_specifiers,
+                missing_args,
+                function,
+            });
+            return None;
+        };
+
+        check_pedantic(&specifiers, &specifier, format_offset, config, errors);
+        let (value, call_arg) =
+            check_value(specifier, specifier_span, &arg, stars, args, config, errors);
+        pairs.push((specifiers.before, value));
+        call_args.push(call_arg);
+    }
+
+    // got args but not associated specifiers, which are passed through as is
+    let excess_args: Vec<Arg<'src>> = args.by_ref().collect();
+    if let (Some(first), Some(last)) = (excess_args.first(), excess_args.last()) {
+        errors.push(match format.is_empty() {
+            // none of the arguments can be printed, e.g. `printf("", x)`
+            true => Error::EmptyFormatWithArgs {
+                format_span,
+                args_span: first.span.start..last.span.end,
+            },
+            false => Error::ExcessArgs {
+                format_span,
+                args_span: args.short_circuit().1,
+                additional_args: excess_args.len(),
+                function,
+            },
+        });
+    }
+    call_args.extend(excess_args.iter().map(|arg| CallArg {
+        arg: args.source(arg.span.clone()),
+        cast: None,
+    }));
+
+    Some((Interpolation::new(pairs, specifiers.remainder), call_args))
+}
+
+/// Pairs the specifiers of a format string with the arguments after it that
+/// they reference by position, like `%2$d`, including the `*` widths and
+/// precisions of ones like `%1$*2$d`, returning them with every argument as
+/// it's passed.
+///
+/// Returns `None` for positions past the last argument. Arguments that
+/// aren't referenced are reported, but passed through as is.
+fn pair_positional<'src>(
+    args: &mut Args<'_, 'src>,
+    format: &'src str,
+    format_span: Range<usize>,
+    function: Function,
+    config: &Config,
+    errors: &mut Vec<Error>,
+) -> Option<(Interpolation<'src, FormatValue<'src>>, Vec<CallArg<'src>>)> {
+    let format_offset = args.offset(format);
+    let call_args: Vec<Arg<'src>> = args.by_ref().collect();
+    let args_span = match (call_args.first(), call_args.last()) {
+        (Some(first), Some(last)) => first.span.start..last.span.end,
+        _ => format_span.clone(),
+    };
+    // how each argument is passed, once it's referenced
+    let mut referenced: Vec<Option<CallArg<'src>>> = call_args.iter().map(|_| None).collect();
+    let mut missing_positions = false;
+    let mut specifiers = Specifiers::new(format);
+    let mut pairs = Vec::with_capacity(4);
+
+    while let Some(specifier) = specifiers.next() {
+        let specifier_span = specifiers.span(format_offset);
+        let options_start =
+            specifier_span.end - specifier.conversion.len() - specifier.options.len();
+        let mut missing_position = |position: usize, errors: &mut Vec<Error>| {
+            missing_positions = true;
+            errors.push(Error::MissingPosition {
+                specifier_span: specifier_span.clone(),
+                args_span: args_span.clone(),
+                position,
+                args: call_args.len(),
+            });
+        };
+
+        // `*` widths and precisions take an `int` argument each, by position too
+        let mut stars = Vec::new();
+        let mut star_args = Vec::new();
+        for (star_offset, position) in specifier.star_positions() {
+            let position = position.expect("stars are all positional without `MixedPositions`");
+            let Some(arg) = position.checked_sub(1).and_then(|i| call_args.get(i)) else {
+                missing_position(position, errors);
+                continue;
+            };
+            let star_span = options_start + star_offset..options_start + star_offset + 1;
+            star_args.push((
+                position,
+                check_star(arg, star_span.clone(), args, config, errors),
+            ));
+            stars.push(star_span);
+        }
+
+        let position = specifier
+            .position
+            .expect("specifiers are all positional without `MixedPositions`");
+        let Some(arg) = position.checked_sub(1).and_then(|i| call_args.get(i)) else {
+            missing_position(position, errors);
+            continue;
+        };
+
+        check_pedantic(&specifiers, &specifier, format_offset, config, errors);
+        let (value, call_arg) =
+            check_value(specifier, specifier_span, arg, stars, args, config, errors);
+        for (position, star_arg) in star_args {
+            referenced[position - 1].get_or_insert(star_arg);
+        }
+        referenced[position - 1].get_or_insert(call_arg);
+        pairs.push((specifiers.before, value));
+    }
+
+    if missing_positions {
+        return None;
+    }
+
+    let unreferenced = referenced
+        .iter()
+        .filter(|call_arg| call_arg.is_none())
+        .count();
+    if unreferenced > 0 {
+        errors.push(Error::ExcessArgs {
+            format_span,
+            args_span,
+            additional_args: unreferenced,
+            function,
+        });
+    }
+
+    // unreferenced args are passed through as is
+    let call_args = referenced
+        .into_iter()
+        .zip(&call_args)
+        .map(|(call_arg, arg)| {
+            call_arg.unwrap_or_else(|| CallArg {
+                arg: args.source(arg.span.clone()),
+                cast: None,
+            })
+        })
+        .collect();
+    Some((Interpolation::new(pairs, specifiers.remainder), call_args))
+}
+
+/// Pushes the errors for specifiers that are only reported with
+/// [`Config::pedantic`], like the `l` in `%lf`.
+fn check_pedantic(
+    specifiers: &Specifiers<'_>,
+    specifier: &Specifier<'_>,
+    format_offset: usize,
+    config: &Config,
+    errors: &mut Vec<Error>,
+) {
+    if !config.pedantic {
+        return;
+    }
+
+    if specifier.ctype == CType::Float && specifier.conversion.starts_with('l') {
+        let modifier_start = specifiers.span(format_offset).end - specifier.conversion.len();
+        errors.push(Error::RedundantLengthModifier(
+            modifier_start..modifier_start + 1,
+        ));
+    }
+
+    if let Some(conversion) = specifiers.repeated_conversion(specifier) {
+        let end = specifiers.span(format_offset).end;
+        errors.push(Error::RepeatedConversion {
+            span: end..end + 1,
+            conversion,
+        });
+    }
+}
+
+/// Checks an argument for a `*` width or precision, which has to be an
+/// `int`, returning how it's passed, and pushing an error if it isn't.
+fn check_star<'src>(
+    arg: &Arg<'src>,
+    star_span: Range<usize>,
+    args: &Args<'_, 'src>,
+    config: &Config,
+    errors: &mut Vec<Error>,
+) -> CallArg<'src> {
+    let arg_source = args.source(arg.span.clone());
+    let inferred_ctype = arg.inferred_ctype(arg_source, config);
+
+    let mismatch = match &arg.cast {
+        Some((cast_ctype, cast_span)) => Some((*cast_ctype, cast_span.clone()))
+            .filter(|(cast_ctype, _)| !cast_ctype.formats_as(CType::Int)),
+        None => inferred_ctype
+            .filter(|ctype| !ctype.formats_as(CType::Int))
+            .map(|ctype| (ctype, arg.span.clone())),
+    };
+    if let Some((arg_ctype, arg_span)) = mismatch {
+        errors.push(Error::StarArgMismatch {
+            star_span,
+            arg_span,
+            arg_ctype,
+        });
+    }
+
+    let typed = arg.cast.is_some() || (config.warn_redundant_casts && inferred_ctype.is_some());
+    CallArg {
+        arg: arg_source,
+        cast: (!typed).then_some(CType::Int),
+    }
+}
+
+/// Checks an argument against the specifier it's formatted with, returning
+/// them as a [`FormatValue`] and how the argument is passed, and pushing
+/// errors if they don't match.
+fn check_value<'src>(
+    specifier: Specifier<'src>,
+    specifier_span: Range<usize>,
+    arg: &Arg<'src>,
+    stars: Vec<Range<usize>>,
+    args: &Args<'_, 'src>,
+    config: &Config,
+    errors: &mut Vec<Error>,
+) -> (FormatValue<'src>, CallArg<'src>) {
+    let arg_source = args.source(arg.span.clone());
+    // infer from what's being casted, e.g. `argv[1]` in `(char*) argv[1]`
+    let casted_source = match &arg.cast {
+        Some((_, cast_span)) => args.source(cast_span.end..arg.span.end),
+        None => arg_source,
+    };
+    let inferred_ctype = arg.inferred_ctype(casted_source.trim_start(), config);
+
+    // check uncasted arguments whose type is obvious, whatever the
+    // options are, e.g. the precision in `%.5s` doesn't make `42` a string,
+    // but an address like `&buf[1]` can point to a string too
+    let any_pointer = specifier.ctype.is_pointer() && parse::is_address_of(arg_source);
+    let mismatch = inferred_ctype
+        .filter(|ctype| arg.cast.is_none() && !any_pointer && !specifier.accepts(*ctype));
+    if let Some(arg_ctype) = mismatch {
+        errors.push(match arg.return_ctype(arg_source, config) {
+            Some(return_ctype) => Error::SpecifierReturnMismatch {
+                specifier_span: specifier_span.clone(),
+                specifier_ctype: specifier.ctype,
+                call_span: arg.span.clone(),
+                return_ctype,
+            },
+            None => Error::SpecifierArgMismatch {
+                specifier_span: specifier_span.clone(),
+                specifier_ctype: specifier.ctype,
+                arg_span: arg.span.clone(),
+                arg_ctype,
+            },
+        });
+    }
+
+    if let Some((cast_ctype, cast_span)) = &arg.cast {
+        if config.warn_redundant_casts && inferred_ctype == Some(*cast_ctype) {
+            errors.push(Error::RedundantCast {
+                cast_span: cast_span.clone(),
+                ctype: *cast_ctype,
+            });
+        } else if config.pedantic && *cast_ctype == specifier.ctype {
+            errors.push(Error::CastMatchesSpecifier {
+                specifier_span: specifier_span.clone(),
+                cast_span: cast_span.clone(),
+                ctype: *cast_ctype,
+            });
+        }
+
+        if !specifier.accepts(*cast_ctype) {
+            errors.push(Error::SpecifierCastMismatch {
+                specifier_span,
+                specifier_ctype: specifier.ctype,
+                cast_span: cast_span.clone(),
+                cast_ctype: *cast_ctype,
+            });
+        }
+    }
+
+    // passed typeck, or obviously has the right type with `--warn-redundant-casts`
+    let typed = arg.cast.is_some()
+        || (config.warn_redundant_casts
+            && inferred_ctype.is_some_and(|ctype| specifier.accepts(ctype)));
+    let call_arg = CallArg {
+        arg: arg_source,
+        cast: (!typed).then_some(specifier.ctype),
+    };
+    let value = FormatValue {
+        arg: arg_source,
+        specifier,
+        stars,
+    };
+    (value, call_arg)
+}
+
+/// Returns [`Error::BufferTooSmall`] if `bufsz` is a constant, possibly
+/// from a macro, that can't fit the text of the format string.
+///
+/// A size of 0 is never too small, since `snprintf(NULL, 0, ...)` is how
+/// the length of the formatted string is measured.
+fn buffer_too_small(
+    bufsz: &str,
+    bufsz_span: Range<usize>,
+    format: &Format<'_>,
+    macros: &Macros<'_>,
+) -> Option<Error> {
+    let bufsz_value = macros::parse_int(bufsz).or_else(|| macros.int(bufsz))?;
+    if bufsz_value == 0 {
+        return None;
+    }
+
+    // text between specifiers, where `%%` prints a single `%`, plus the null terminator
+    let raw = format.opening.ends_with('(');
+    let required = format
+        .interpolation
+        .pairs
+        .iter()
+        .map(|(chunk, _)| *chunk)
+        .chain([format.interpolation.last])
+        .map(|chunk| {
+            // raw strings don't have escapes or adjacent literals
+            let len = match raw {
+                true => chunk.len(),
+                false => macros::unescaped_len(chunk),
+            };
+            len - chunk.matches("%%").count()
+        })
+        .sum::<usize>() as u64
+        + 1;
+
+    (bufsz_value < required).then_some(Error::BufferTooSmall {
+        bufsz_span,
+        bufsz: bufsz_value,
+        format_span: format.span(),
+        required,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Standard;
+    use crate::rules::Level;
+    use miette::Diagnostic;
+
+    /// Returns the rules of the errors in source code, if it's invalid.
+    fn errors(source: &str, config: &Config) -> Vec<&'static str> {
+        match IntermediateRepresentation::parse(source, config) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors.iter().map(|error| error.error().rule()).collect(),
+        }
+    }
+
+    /// Returns the rules of the warnings and advice in valid source code.
+    fn warnings(source: &str, config: &Config) -> Vec<&'static str> {
+        IntermediateRepresentation::parse(source, config)
+            .expect("source code is valid")
+            .into_warnings()
+            .iter()
+            .map(|warning| warning.error().rule())
+            .collect()
+    }
+
+    /// Returns valid source code with type casts added.
+    fn typecast(source: &str, config: &Config) -> String {
+        IntermediateRepresentation::parse(source, config)
+            .expect("source code is valid")
+            .display_typecast()
+            .to_string()
+    }
+
+    #[test]
+    fn typedef_casts() {
+        let mut config = Config::default();
+        config.typedefs.insert("int32_t".to_string(), CType::Int);
+
+        let source = r#"printf("%d", (int32_t) x);"#;
+        assert_eq!(typecast(source, &config), source);
+        assert_eq!(
+            errors(r#"printf("%s", (int32_t) x);"#, &config),
+            ["specifier-cast-mismatch"]
+        );
+        config
+            .typedefs
+            .insert("uint32_t".to_string(), CType::Unsigned);
+        let source = r#"printf("%u %x", (uint32_t) x, (uint32_t) y);"#;
+        assert_eq!(typecast(source, &config), source);
+        assert_eq!(
+            errors(r#"printf("%d", (uint32_t) x);"#, &config),
+            ["specifier-cast-mismatch"]
+        );
+        // unregistered names aren't casts, so nothing is known about `x`
+        assert!(
+            IntermediateRepresentation::parse(r#"printf("%s", (int64_t) x);"#, &config).is_ok()
+        );
+    }
+
+    #[test]
+    fn redundant_casts() {
+        let config = Config {
+            warn_redundant_casts: true,
+            ..Config::default()
+        };
+        let source = r#"printf("%d %s", (int) 5, (char*) "a");"#;
+        assert_eq!(
+            warnings(source, &config),
+            ["redundant-cast", "redundant-cast"]
+        );
+        // the call is still rewritten, with the casts it already has
+        assert_eq!(typecast(source, &config), source);
+        assert!(warnings(r#"printf("%d", (int) x);"#, &config).is_empty());
+        assert!(warnings(source, &Config::default()).is_empty());
+
+        // literals that obviously have the right type aren't casted
+        assert_eq!(
+            typecast(r#"printf("%d %s %d", 5, "a", x);"#, &config),
+            r#"printf("%d %s %d", 5, "a", (int) (x));"#
+        );
+        assert_eq!(
+            typecast(r#"printf("%d", 5);"#, &Config::default()),
+            r#"printf("%d", (int) (5));"#
+        );
+    }
+
+    #[test]
+    fn switch_cases_are_kept_byte_exact() {
+        let source = "switch (x) {\n\
+                      case 1: printf(\"one %d\\n\", x); break;\n\
+                      case 2:printf(\"two\\n\");\n\
+                      default: { printf(\"%s\", s); }\n\
+                      }\n";
+        assert_eq!(
+            typecast(source, &Config::default()),
+            "switch (x) {\n\
+             case 1: printf(\"one %d\\n\", (int) (x)); break;\n\
+             case 2:printf(\"two\\n\");\n\
+             default: { printf(\"%s\", (char*) (s)); }\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn adjacent_calls() {
+        let config = Config::default();
+        assert_eq!(
+            typecast(r#"printf("%d",a);printf("%s",b);"#, &config),
+            r#"printf("%d", (int) (a));printf("%s", (char*) (b));"#
+        );
+        let source = r#"printf("%d", x)||printf("%s", s);printf("a"),printf("%d", y)"#;
+        assert_eq!(
+            typecast(source, &config),
+            r#"printf("%d", (int) (x))||printf("%s", (char*) (s));printf("a"),printf("%d", (int) (y))"#
+        );
+
+        // a rejected format string doesn't hide the call after it
+        assert_eq!(
+            errors(r#"printf(fmt);printf("%d");"#, &config),
+            ["nonliteral-format", "excess-specifiers"]
+        );
+    }
+
+    #[test]
+    fn modernize_respells_specifiers() {
+        let config = Config::default();
+        let modernize = |source: &str| {
+            IntermediateRepresentation::parse(source, &config)
+                .expect("source code is valid")
+                .display_modernize()
+                .to_string()
+        };
+        assert_eq!(
+            modernize(r#"printf("%i %5i\n", a, b);"#),
+            r#"printf("%d %5d\n", a, b);"#
+        );
+        assert_eq!(
+            modernize(r#"printf("%+-5d|%-+-3.1f|%+s", a, b, c);"#),
+            r#"printf("%-+5d|%-+3.1f|%+s", a, b, c);"#
+        );
+        assert_eq!(
+            modernize(r#"printf("%0#-8x|% +d", a, b);"#),
+            r#"printf("%-#08x|%+ d", a, b);"#
+        );
+        assert_eq!(
+            modernize(r#"printf("%d %5.2f", a, b);"#),
+            r#"printf("%d %5.2f", a, b);"#
+        );
+        assert_eq!(
+            modernize(r#"printf("%D %lli", (long) a, b);"#),
+            r#"printf("%ld %lld", (long) a, b);"#
+        );
+    }
+
+    #[test]
+    fn argv_index_is_string() {
+        let config = Config {
+            warn_redundant_casts: true,
+            ..Config::default()
+        };
+        assert_eq!(
+            warnings(r#"printf("%s", (char*) argv[i + 1]);"#, &config),
+            ["redundant-cast"]
+        );
+        assert_eq!(
+            typecast(r#"printf("%s %s", argv[0], argv [1]);"#, &config),
+            r#"printf("%s %s", argv[0], argv [1]);"#
+        );
+        // a character of an argument isn't a string
+        assert_eq!(
+            typecast(r#"printf("%s", argv[0][1]);"#, &config),
+            r#"printf("%s", (char*) (argv[0][1]));"#
+        );
+    }
+
+    #[test]
+    fn buffer_too_small_resolves_defines() {
+        let config = Config::default();
+        let source = "#define MAXLEN 4\nsnprintf(buf, MAXLEN, \"value: %d\", x);";
+        assert_eq!(warnings(source, &config), ["buffer-too-small"]);
+        // only a warning, so the call is still rewritten
+        assert_eq!(
+            typecast(source, &config),
+            "#define MAXLEN 4\nsnprintf((char* restrict) (buf), (size_t) (MAXLEN), \"value: %d\", (int) (x));"
+        );
+
+        let source = "#define MAXLEN 64\nsnprintf(buf, MAXLEN, \"value: %d\", x);";
+        assert!(warnings(source, &config).is_empty());
+
+        // not a constant, so the size isn't checked
+        let source = "#define MAXLEN (len + 1)\nsnprintf(buf, MAXLEN, \"value: %d\", x);";
+        assert!(warnings(source, &config).is_empty());
+    }
+
+    #[test]
+    fn buffer_too_small_skips_measuring() {
+        let source = r#"int len = snprintf(NULL, 0, "value: %d", x);"#;
+        assert!(warnings(source, &Config::default()).is_empty());
+    }
+
+    #[test]
+    fn buffer_too_small_counts_bytes() {
+        let warnings =
+            IntermediateRepresentation::parse(r#"snprintf(buf, 1, "ab");"#, &Config::default())
+                .expect("source code is valid")
+                .into_warnings();
+        let [warning] = &warnings[..] else {
+            panic!("expected one warning");
+        };
+        let labels: Vec<_> = warning
+            .labels()
+            .unwrap()
+            .filter_map(|label| label.label().map(str::to_string))
+            .collect();
+        assert_eq!(labels, ["buffer holds 1 byte", "needs at least 3 bytes"]);
+        assert_eq!(
+            warning.help().unwrap().to_string(),
+            "Make the buffer at least 3 bytes."
+        );
+    }
+
+    #[test]
+    fn format_source_slices_source_code() {
+        let source = "int x;\nprintf( \"x = %d\\n\" , x);\nsprintf(buf, \"%s\"\n  \"!\", s);";
+        let ir = IntermediateRepresentation::parse(source, &Config::default()).unwrap();
+        let formats: Vec<&Format> = ir.sites().map(Site::format).collect();
+
+        assert_eq!(formats[0].source(), r"x = %d\n");
+        assert_eq!(&source[formats[0].span()], r#""x = %d\n""#);
+        assert_eq!(&source[formats[1].span()], "\"%s\"\n  \"!\"");
+
+        // whitespace after the literal isn't part of it
+        assert_eq!(
+            typecast(source, &Config::default()),
+            "int x;\nprintf(\"x = %d\\n\", (int) (x));\nsprintf((char* restrict) (buf), \"%s\"\n  \"!\", (char*) (s));"
+        );
+    }
+
+    #[test]
+    fn redundant_length_modifier_is_pedantic() {
+        let source = r#"printf("%lf %-5.1lf", x, y);"#;
+        assert!(warnings(source, &Config::default()).is_empty());
+
+        let config = Config {
+            pedantic: true,
+            ..Config::default()
+        };
+        let warnings = IntermediateRepresentation::parse(source, &config)
+            .expect("source code is valid")
+            .into_warnings();
+        let spans: Vec<_> = warnings
+            .iter()
+            .map(|warning| match warning.error() {
+                Error::RedundantLengthModifier(span) => &source[span.clone()],
+                warning => panic!("unexpected {warning:?}"),
+            })
+            .collect();
+        assert_eq!(spans, ["l", "l"]);
+        // only advice, so the call is still rewritten
+        assert_eq!(
+            typecast(source, &config),
+            r#"printf("%lf %-5.1lf", (float) (x), (float) (y));"#
+        );
+    }
+
+    #[test]
+    fn fn_returns_are_checked() {
+        let mut config = Config::default();
+        config
+            .fn_returns
+            .insert("get_count".to_string(), CType::Int);
+        let is_valid = |source: &str| IntermediateRepresentation::parse(source, &config).is_ok();
+
+        assert!(is_valid(r#"printf("%d", get_count());"#));
+        assert!(is_valid(r#"printf("%d", get_count(a, f(b)));"#));
+        assert_eq!(
+            errors(r#"printf("%s", get_count());"#, &config),
+            ["specifier-return-mismatch"]
+        );
+        // not a single call, so the type isn't known
+        assert!(is_valid(r#"printf("%s", get_count() + 1);"#));
+        assert!(is_valid(r#"printf("%s", get_other());"#));
+    }
+
+    #[test]
+    fn ms_compat_wide_conversions() {
+        let source = r#"printf("%C %S", (wint_t) c, (wchar_t*) s);"#;
+        // without the flag they're reported as unknown
+        assert_eq!(
+            errors(source, &Config::default()),
+            ["unknown-conversion", "unknown-conversion"]
+        );
+
+        let config = Config {
+            ms_compat: true,
+            ..Config::default()
+        };
+        assert!(IntermediateRepresentation::parse(source, &config).is_ok());
+        assert_eq!(
+            errors(r#"printf("%S", (char*) s);"#, &config),
+            ["specifier-cast-mismatch"]
+        );
+        assert_eq!(
+            typecast(r#"printf("%C", c);"#, &config),
+            r#"printf("%C", (wint_t) (c));"#
+        );
+    }
+
+    #[test]
+    fn c23_binary_conversions() {
+        let source = r#"printf("%b %08lB", (unsigned) x, (long) y);"#;
+        // without C23 they're reported as unsupported
+        assert_eq!(
+            errors(source, &Config::default()),
+            ["unsupported-specifier", "unsupported-specifier"]
+        );
+
+        let config = Config {
+            standard: Standard::C23,
+            ..Config::default()
+        };
+        assert!(IntermediateRepresentation::parse(source, &config).is_ok());
+        assert_eq!(
+            errors(r#"printf("%b", (char*) s);"#, &config),
+            ["specifier-cast-mismatch"]
+        );
+        // the conversion letter is kept as written
+        assert_eq!(
+            typecast(r#"printf("%b %B", x, y);"#, &config),
+            r#"printf("%b %B", (int) (x), (int) (y));"#
+        );
+        let ir = IntermediateRepresentation::parse(r#"printf("%b", x);"#, &config).unwrap();
+        assert_eq!(
+            ir.display_optimize().to_string(),
+            r#"safe_printf(4, "", (void*) &(x), fmt_binary, "");"#
+        );
+    }
+
+    #[test]
+    fn site_locations_across_lines() {
+        let source = "int main() {\n    printf(\"%d\\n\",\n           x);\n\tprintf(\"a\");\n}";
+        let ir = IntermediateRepresentation::parse(source, &Config::default()).unwrap();
+        let locations: Vec<(usize, usize)> = ir
+            .sites()
+            .map(|site| (site.location().line, site.location().column))
+            .collect();
+        assert_eq!(locations, [(2, 5), (4, 2)]);
+    }
+
+    #[test]
+    fn raw_string_formats() {
+        let source = r#"printf(R"(%d "quoted")", x); printf(R"x(%s)")x", s);"#;
+        let invalid = IntermediateRepresentation::parse(source, &Config::default())
+            .expect_err("source code is invalid");
+        assert_eq!(invalid.len(), 2);
+        assert_eq!(invalid[0].error().rule(), "nonliteral-format");
+        assert_eq!(
+            invalid[0].help().unwrap().to_string(),
+            "Raw string literals are only supported with `--cpp`."
+        );
+
+        let config = Config {
+            cpp: true,
+            ..Config::default()
+        };
+        assert_eq!(
+            typecast(source, &config),
+            r#"printf(R"(%d "quoted")", (int) (x)); printf(R"x(%s)")x", (char*) (s));"#
+        );
+        assert_eq!(
+            warnings(r#"printf(R"(%d\n)", s, t);"#, &config),
+            ["excess-args"]
+        );
+    }
+
+    #[test]
+    fn check_buffers() {
+        let sources = [
+            r#"sprintf(5, "%d", x);"#,
+            r#"sprintf((int) buf, "%d", x);"#,
+            r#"snprintf(2.5, 10, "%d", x);"#,
+        ];
+        for source in sources {
+            assert!(
+                IntermediateRepresentation::parse(source, &Config::default()).is_ok(),
+                "{source}"
+            );
+        }
+
+        let config = Config {
+            check_buffers: true,
+            ..Config::default()
+        };
+        for source in sources {
+            assert_eq!(errors(source, &config), ["non-pointer-buffer"], "{source}");
+        }
+        assert!(IntermediateRepresentation::parse(r#"sprintf(buf, "%d", x);"#, &config).is_ok());
+        assert!(
+            IntermediateRepresentation::parse(r#"sprintf((char*) p, "%d", x);"#, &config).is_ok()
+        );
+    }
+
+    #[test]
+    fn transform_stats_count_casts() {
+        let source = r#"printf("%d %s\n", x, (char*) s); printf("%d", 5); printf("a");"#;
+        let stats = |config: &Config| {
+            IntermediateRepresentation::parse(source, config)
+                .expect("source code is valid")
+                .stats()
+        };
+
+        let default = stats(&Config::default());
+        assert_eq!(
+            default,
+            TransformStats {
+                sites: 3,
+                casts_added: 2,
+                args_unchanged: 1,
+            }
+        );
+        assert_eq!(
+            default.to_string(),
+            "rewrote 3 call sites, added 2 casts, left 1 argument unchanged"
+        );
+
+        let redundant = stats(&Config {
+            warn_redundant_casts: true,
+            ..Config::default()
+        });
+        assert_eq!(
+            redundant.to_string(),
+            "rewrote 3 call sites, added 1 cast, left 2 arguments unchanged"
+        );
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let nested = |depth: usize| {
+            format!(
+                r#"printf("%d", {}x{});"#,
+                "(".repeat(depth),
+                ")".repeat(depth)
+            )
+        };
+        let excessive =
+            |source: &str, config: &Config| errors(source, config) == ["excessive-nesting"];
+        let config = Config {
+            max_nesting: 8,
+            ..Config::default()
+        };
+        assert!(IntermediateRepresentation::parse(&nested(8), &config).is_ok());
+        assert!(excessive(&nested(9), &config));
+
+        // deep nesting is reported rather than overflowing the stack
+        assert!(excessive(&nested(100_000), &Config::default()));
+    }
+
+    #[test]
+    fn unknown_conversions() {
+        let errors =
+            IntermediateRepresentation::parse(r#"printf("%q %5y", x, y);"#, &Config::default())
+                .expect_err("source code is invalid");
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].error().rule(), "unknown-conversion");
+        assert_eq!(errors[0].offset(), 8);
+        assert_eq!(errors[1].offset(), 11);
+    }
+
+    #[test]
+    fn promoted_casts() {
+        let config = Config::default();
+        for source in [
+            r#"printf("%d", (char) c);"#,
+            r#"printf("%d", (short) s);"#,
+            r#"printf("%f", (double) d);"#,
+            r#"printf("%f", (float) f);"#,
+        ] {
+            assert!(
+                IntermediateRepresentation::parse(source, &config).is_ok(),
+                "{source}"
+            );
+        }
+        for source in [
+            r#"printf("%s", (char) c);"#,
+            r#"printf("%d", (double) d);"#,
+            r#"printf("%f", (short) s);"#,
+        ] {
+            assert_eq!(
+                errors(source, &config),
+                ["specifier-cast-mismatch"],
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn deps_list_resolved_macros() {
+        let source = "#define SIZE 16\n\
+                      #define UNUSED 5\n\
+                      snprintf(buf, SIZE, \"%d\", x);\n\
+                      printf(\"%d\", y);";
+        let ir = IntermediateRepresentation::parse(source, &Config::default()).unwrap();
+        assert_eq!(ir.deps(), [("SIZE", "16")]);
+
+        let ir =
+            IntermediateRepresentation::parse(r#"printf("%d", x);"#, &Config::default()).unwrap();
+        assert!(ir.deps().is_empty());
+    }
+
+    #[test]
+    fn literal_ternary_formats() {
+        for source in [
+            r#"printf(cond ? "%d" : "%s", x);"#,
+            r#"printf(cond ? (char*) "%d" : (const char*) "%s", x);"#,
+            r#"printf((cond ? "%d" : "%s"), x);"#,
+            r#"printf(cond ? "a: %d" : "%s", x);"#,
+            r#"printf(cond ? "%d?" : "b: %s", x);"#,
+            r#"printf(cond ? "a ? %d : b" : "%s", x);"#,
+            r#"printf(flags[a ? b : c] ? "%d" : "%s", x);"#,
+        ] {
+            let errors = IntermediateRepresentation::parse(source, &Config::default())
+                .expect_err("source code is invalid");
+            assert_eq!(errors[0].error().rule(), "nonliteral-format", "{source}");
+            let help = errors[0].help().expect("error has help").to_string();
+            assert!(
+                help.starts_with("Both branches are string literals"),
+                "{source}"
+            );
+        }
+
+        let errors = IntermediateRepresentation::parse(
+            r#"printf(cond ? "%d" : fmt, x);"#,
+            &Config::default(),
+        )
+        .expect_err("source code is invalid");
+        let help = errors[0].help().expect("error has help").to_string();
+        assert!(!help.starts_with("Both branches"), "{help}");
+    }
+
+    #[test]
+    fn optimize_escapes_ascii_control_characters() {
+        let source = "printf(\"a\tb\x01%d\x7f \u{85}\u{e9}\", x);";
+        let ir = IntermediateRepresentation::parse(source, &Config::default())
+            .expect("source code is valid");
+        assert_eq!(
+            ir.display_optimize().to_string(),
+            "safe_printf(4, \"a\\tb\\001\", (void*) &(x), fmt_int, \"\\177 \u{85}\u{e9}\");"
+        );
+    }
+
+    #[test]
+    fn optimize_keeps_escaped_newlines() {
+        let optimize = |source: &str| {
+            IntermediateRepresentation::parse(source, &Config::default())
+                .expect("source code is valid")
+                .display_optimize()
+                .to_string()
+        };
+        assert_eq!(
+            optimize(r#"printf("a\n%d\tb\n", x);"#),
+            r#"safe_printf(4, "a\n", (void*) &(x), fmt_int, "\tb\n");"#
+        );
+        // a chunk spanning literals on several lines keeps them as they are
+        assert_eq!(
+            optimize("printf(\"one\\n\"\n       \"%s\\n\", s);"),
+            "safe_printf(4, \"one\\n\"\n       \"\", (void*) (s), fmt_string, \"\\n\");"
+        );
+    }
+
+    #[test]
+    fn string_precision_needs_string() {
+        let config = Config::default();
+        assert_eq!(
+            errors(r#"printf("%.5s", 42);"#, &config),
+            ["specifier-arg-mismatch"]
+        );
+        assert_eq!(
+            errors(r#"printf("%.5s", (int) x);"#, &config),
+            ["specifier-cast-mismatch"]
+        );
+        assert!(
+            IntermediateRepresentation::parse(r#"printf("%.5s %s", s, "abcdef");"#, &config)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn calls_without_semicolons() {
+        let config = Config::default();
+        for (source, expected) in [
+            (
+                r#"f(printf("%d", x), y);"#,
+                r#"f(printf("%d", (int) (x)), y);"#,
+            ),
+            (
+                r#"(void) (printf("%d", x))"#,
+                r#"(void) (printf("%d", (int) (x)))"#,
+            ),
+            (
+                r#"return printf("%d", x)"#,
+                r#"return printf("%d", (int) (x))"#,
+            ),
+            (
+                "n = printf(\"%s\", s)\n}",
+                "n = printf(\"%s\", (char*) (s))\n}",
+            ),
+        ] {
+            assert_eq!(typecast(source, &config), expected);
+        }
+
+        assert_eq!(errors(r#"printf("%d", x"#, &config), ["unterminated-call"]);
+    }
+
+    #[test]
+    fn pointer_specifier_casts() {
+        let config = Config::default();
+        for source in [
+            r#"printf("%p", (void*) p);"#,
+            r#"printf("%p", (char*) s);"#,
+            r#"printf("%p", argv);"#,
+        ] {
+            assert!(
+                IntermediateRepresentation::parse(source, &config).is_ok(),
+                "{source}"
+            );
+        }
+        assert_eq!(
+            errors(r#"printf("%p", (int) p);"#, &config),
+            ["specifier-cast-mismatch"]
+        );
+        assert_eq!(
+            typecast(r#"printf("%p", p);"#, &config),
+            r#"printf("%p", (void*) (p));"#
+        );
+    }
+
+    #[test]
+    fn address_of_is_any_pointer() {
+        let config = Config::default();
+        for source in [r#"printf("%p", &x);"#, r#"printf("%s", &buf[1]);"#] {
+            assert!(
+                IntermediateRepresentation::parse(source, &config).is_ok(),
+                "{source}"
+            );
+        }
+        for source in [r#"printf("%d", &x);"#, r#"printf("%p", 5);"#] {
+            assert_eq!(
+                errors(source, &config),
+                ["specifier-arg-mismatch"],
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn oneline_collapses_whitespace() {
+        let source = "snprintf(buf,\n         sizeof buf,\n         \"%d  items\\n\",   /* count */\n         n + 1);";
+        let ir = IntermediateRepresentation::parse(source, &Config::default()).unwrap();
+        let site = ir.sites().next().unwrap();
+        assert_eq!(
+            site.oneline(),
+            r#"snprintf(buf, sizeof buf, "%d  items\n", /* count */ n + 1)"#
+        );
+
+        let ir =
+            IntermediateRepresentation::parse(r#"fprintf(out, "%*d", w, x);"#, &Config::default())
+                .unwrap();
+        let site = ir.sites().next().unwrap();
+        assert_eq!(site.oneline(), r#"fprintf(out, "%*d", w, x)"#);
+    }
+
+    #[test]
+    fn repeated_conversion_is_pedantic() {
+        let source = r#"printf("%dd %ss", x, s);"#;
+        assert!(warnings(source, &Config::default()).is_empty());
+
+        let config = Config {
+            pedantic: true,
+            ..Config::default()
+        };
+        assert_eq!(
+            warnings(source, &config),
+            ["repeated-conversion", "repeated-conversion"]
+        );
+        assert!(warnings(r#"printf("%d days %s", x, s);"#, &config).is_empty());
+        // only advice, so the call is still rewritten
+        assert_eq!(
+            typecast(source, &config),
+            r#"printf("%dd %ss", (int) (x), (char*) (s));"#
+        );
+    }
+
+    #[test]
+    fn defines_are_not_validated() {
+        let source = "#define X(name) \\\n    printf(\"%s\\n\", name); \\\n    printf(\"%d\")\n\
+                      #define LOG(...) printf(__VA_ARGS__)\n\
+                      printf(\"%d\", x);";
+        let ir = IntermediateRepresentation::parse(source, &Config::default()).unwrap();
+        assert_eq!(ir.sites().count(), 1);
+        assert_eq!(
+            ir.display_typecast().to_string(),
+            source.replace(r#"printf("%d", x);"#, r#"printf("%d", (int) (x));"#)
+        );
+    }
+
+    #[test]
+    fn unknown_conversions_keep_length_modifier() {
+        let source = r#"printf("%*lq %llq", w, c, c);"#;
+        let errors = IntermediateRepresentation::parse(source, &Config::default())
+            .expect_err("source code is invalid");
+        let conversions: Vec<_> = errors
+            .iter()
+            .map(|error| match error.error() {
+                Error::UnknownConversion { conversion, .. } => conversion.as_str(),
+                error => panic!("unexpected {error:?}"),
+            })
+            .collect();
+        assert_eq!(conversions, ["%*lq", "%llq"]);
+    }
+
+    #[test]
+    fn hexadecimal_specifiers() {
+        let config = Config::default();
+        let source = r#"printf("%x %-8X", a, (int) b);"#;
+        assert_eq!(
+            typecast(source, &config),
+            r#"printf("%x %-8X", (int) (a), (int) b);"#
+        );
+        let ir = IntermediateRepresentation::parse(source, &config).unwrap();
+        assert_eq!(ir.display_modernize().to_string(), source);
+        let ir = IntermediateRepresentation::parse(r#"printf("%x %X", a, b);"#, &config).unwrap();
+        assert_eq!(
+            ir.display_optimize().to_string(),
+            r#"safe_printf(7, "", (void*) &(a), fmt_hex, " ", (void*) &(b), fmt_hex_upper, "");"#
+        );
+
+        assert_eq!(
+            errors(r#"printf("%x", (char*) s);"#, &config),
+            ["specifier-cast-mismatch"]
+        );
+    }
+
+    #[test]
+    fn char_specifier() {
+        let config = Config::default();
+        for source in [
+            r#"printf("%c", (char) c);"#,
+            r#"printf("%c %d", 'x', 'y');"#,
+        ] {
+            assert!(
+                IntermediateRepresentation::parse(source, &config).is_ok(),
+                "{source}"
+            );
+        }
+        assert_eq!(
+            errors(r#"printf("%c", (int) x);"#, &config),
+            ["specifier-cast-mismatch"]
+        );
+
+        assert_eq!(
+            typecast(r#"printf("%-3c", c);"#, &config),
+            r#"printf("%-3c", (char) (c));"#
+        );
+        let ir = IntermediateRepresentation::parse(r#"printf("%c", c);"#, &config).unwrap();
+        assert_eq!(
+            ir.display_optimize().to_string(),
+            r#"safe_printf(4, "", (void*) &(char){c}, fmt_char, "");"#
+        );
+    }
+
+    #[test]
+    fn optimize_char_literals() {
+        let config = Config::default();
+        assert_eq!(
+            typecast(r#"printf("%c", 'x');"#, &config),
+            r#"printf("%c", (char) ('x'));"#
+        );
+        assert_eq!(
+            IntermediateRepresentation::parse(r#"printf("%c", 'x');"#, &config)
+                .expect("source code is valid")
+                .display_optimize()
+                .to_string(),
+            r#"safe_printf(4, "", (void*) &(char){'x'}, fmt_char, "");"#
+        );
+    }
+
+    #[test]
+    fn unsigned_specifier() {
+        let config = Config::default();
+        for source in [
+            r#"printf("%u", (unsigned) x);"#,
+            r#"printf("%u", (unsigned int) x);"#,
+            r#"printf("%u %x", 5u, 5U);"#,
+            r#"printf("%x %X", (int) x, (unsigned) y);"#,
+        ] {
+            assert!(
+                IntermediateRepresentation::parse(source, &config).is_ok(),
+                "{source}"
+            );
+        }
+        assert_eq!(
+            errors(r#"printf("%u", (int) x);"#, &config),
+            ["specifier-cast-mismatch"]
+        );
+        assert_eq!(
+            errors(r#"printf("%d", 5u);"#, &config),
+            ["specifier-arg-mismatch"]
+        );
+
+        assert_eq!(
+            typecast(r#"printf("%u", x);"#, &config),
+            r#"printf("%u", (unsigned) (x));"#
+        );
+        let ir = IntermediateRepresentation::parse(r#"printf("%u", x);"#, &config).unwrap();
+        assert_eq!(
+            ir.display_optimize().to_string(),
+            r#"safe_printf(4, "", (void*) &(x), fmt_uint, "");"#
+        );
+    }
+
+    #[test]
+    fn spaced_unsigned_casts() {
+        let config = Config::default();
+        let source = "printf(\"%u %u\", ( unsigned ) x, (unsigned\n int) y);";
+        assert_eq!(typecast(source, &config), source);
+        assert_eq!(
+            errors(r#"printf("%s", ( unsigned  int ) x);"#, &config),
+            ["specifier-cast-mismatch"]
+        );
+    }
+
+    #[test]
+    fn octal_specifier() {
+        let config = Config::default();
+        let source = r#"printf("%o %5o", (int) x, (unsigned) y);"#;
+        let ir = IntermediateRepresentation::parse(source, &config).unwrap();
+        assert_eq!(ir.display_modernize().to_string(), source);
+        assert_eq!(
+            typecast(r#"printf("%o", x);"#, &config),
+            r#"printf("%o", (int) (x));"#
+        );
+        let ir = IntermediateRepresentation::parse(r#"printf("%o", x);"#, &config).unwrap();
+        assert_eq!(
+            ir.display_optimize().to_string(),
+            r#"safe_printf(4, "", (void*) &(x), fmt_octal, "");"#
+        );
+        assert_eq!(
+            errors(r#"printf("%o %o", (char*) s);"#, &config),
+            ["excess-specifiers", "specifier-cast-mismatch"]
+        );
+    }
+
+    #[test]
+    fn float_specifiers_keep_their_letter() {
+        let config = Config::default();
+        assert_eq!(
+            typecast(r#"printf("%.3e\n", x);"#, &config),
+            r#"printf("%.3e\n", (float) (x));"#
+        );
+        assert_eq!(
+            typecast(r#"printf("%E %g %G %a %A %F", a, b, c, d, e, f);"#, &config),
+            r#"printf("%E %g %G %a %A %F", (float) (a), (float) (b), (float) (c), (float) (d), (float) (e), (float) (f));"#
+        );
+        assert!(errors(r#"printf("%g", (double) x);"#, &config).is_empty());
+        assert_eq!(
+            errors(r#"printf("%e", (int) x);"#, &config),
+            ["specifier-cast-mismatch"]
+        );
+
+        let source = r#"printf("%le %lg", x, y);"#;
+        let ir = IntermediateRepresentation::parse(source, &config).unwrap();
+        assert_eq!(
+            ir.display_modernize().to_string(),
+            r#"printf("%e %g", x, y);"#
+        );
+        let pedantic = Config {
+            pedantic: true,
+            ..Config::default()
+        };
+        assert_eq!(
+            warnings(source, &pedantic),
+            ["redundant-length-modifier", "redundant-length-modifier"]
+        );
+    }
+
+    #[test]
+    fn spaced_void_pointer_cast() {
+        let config = Config::default();
+        let source = r#"printf("%p", (void *) ptr);"#;
+        assert_eq!(typecast(source, &config), source);
+        assert_eq!(
+            errors(r#"printf("%d", (void *) ptr);"#, &config),
+            ["specifier-cast-mismatch"]
+        );
+    }
+
+    #[test]
+    fn percent_escapes() {
+        let config = Config::default();
+        assert!(errors(r#"printf("%%d\n");"#, &config).is_empty());
+        assert_eq!(warnings(r#"printf("%%d\n", x);"#, &config), ["excess-args"]);
+        assert_eq!(
+            typecast(r#"printf("%%d\n");"#, &config),
+            r#"printf("%%d\n");"#
+        );
+        assert_eq!(
+            typecast(r#"printf("%d%%\n", x);"#, &config),
+            r#"printf("%d%%\n", (int) (x));"#
+        );
+        assert_eq!(
+            typecast(r#"printf("100%% %%%s", s);"#, &config),
+            r#"printf("100%% %%%s", (char*) (s));"#
+        );
+
+        let ir = IntermediateRepresentation::parse(r#"printf("%d%%\n", x);"#, &config).unwrap();
+        assert_eq!(
+            ir.display_optimize().to_string(),
+            r#"safe_printf(4, "", (void*) &(x), fmt_int, "%\n");"#
+        );
+        // `100%` and the null terminator
+        assert!(warnings(r#"snprintf(buf, 5, "100%%");"#, &config).is_empty());
+        assert_eq!(
+            warnings(r#"snprintf(buf, 4, "100%%");"#, &config),
+            ["buffer-too-small"]
+        );
+    }
+
+    #[test]
+    fn lone_percent_is_reported() {
+        let config = Config::default();
+        assert_eq!(
+            errors(r#"printf("100%");"#, &config),
+            ["unknown-conversion"]
+        );
+        assert_eq!(
+            typecast(r#"printf("%d%% off", n);"#, &config),
+            r#"printf("%d%% off", (int) (n));"#
+        );
+    }
+
+    #[test]
+    fn write_back_is_rejected() {
+        let config = Config::default();
+        assert_eq!(
+            errors(r#"printf("%n", &count);"#, &config),
+            ["write-back-specifier"]
+        );
+        assert_eq!(
+            errors(r#"printf("%d%hhn\n", x, &count);"#, &config),
+            ["write-back-specifier"]
+        );
+    }
+
+    #[test]
+    fn scientific_float_specifiers() {
+        let config = Config::default();
+        assert_eq!(
+            IntermediateRepresentation::parse(r#"printf("%e %G", x, y);"#, &config)
+                .expect("source code is valid")
+                .display_optimize()
+                .to_string(),
+            r#"safe_printf(7, "", (void*) &(x), fmt_float, " ", (void*) &(y), fmt_float, "");"#
+        );
+        assert_eq!(
+            errors(r#"printf("%.3Le", (double) x);"#, &config),
+            ["specifier-cast-mismatch"]
+        );
+    }
+
+    #[test]
+    fn length_modifiers_match_literal_suffixes() {
+        let config = Config::default();
+        for source in [
+            r#"printf("%ld", 5L);"#,
+            r#"printf("%lu", 5UL);"#,
+            r#"printf("%llu", 5ULL);"#,
+            r#"printf("%lld", 5LL);"#,
+            r#"printf("%lx", 5UL);"#,
+            r#"printf("%ld %lu", (5L), ( 5UL ));"#,
+            r#"printf("%lu", (unsigned long) x);"#,
+            r#"printf("%hd %hhd", (int) x, 5);"#,
+            r#"printf("%zu", sizeof(int));"#,
+            r#"printf("%zu", sizeof x);"#,
+        ] {
+            assert!(errors(source, &config).is_empty(), "{source}");
+        }
+
+        assert_eq!(
+            errors(r#"printf("%d", 5L);"#, &config),
+            ["specifier-arg-mismatch"]
+        );
+        assert_eq!(
+            errors(r#"printf("%d", (5L));"#, &config),
+            ["specifier-arg-mismatch"]
+        );
+        assert_eq!(
+            errors(r#"printf("%ld", (unsigned long) x);"#, &config),
+            ["specifier-cast-mismatch"]
+        );
+    }
+
+    #[test]
+    fn mismatch_help_uses_literal_suffix() {
+        let Err(errors) =
+            IntermediateRepresentation::parse(r#"printf("%d", 5L);"#, &Config::default())
+        else {
+            panic!("expected errors");
+        };
+        let help = errors[0].error().help().map(|help| help.to_string());
+        assert_eq!(help.as_deref(), Some("Change the specifier to `%ld`."));
+    }
+
+    #[test]
+    fn long_double_literal() {
+        let config = Config::default();
+        assert!(errors(r#"printf("%Lf", 1.0L);"#, &config).is_empty());
+        assert!(errors(r#"printf("%Lf", (1.0L));"#, &config).is_empty());
+        assert!(errors(r#"printf("%-10.2Lf", 2.5e3l);"#, &config).is_empty());
+        assert_eq!(
+            errors(r#"printf("%f", 1.0L);"#, &config),
+            ["specifier-arg-mismatch"]
+        );
+        assert_eq!(
+            errors(r#"printf("%Lf", 1.0);"#, &config),
+            ["specifier-arg-mismatch"]
+        );
+        assert_eq!(
+            errors(r#"printf("%Lf", (double) x);"#, &config),
+            ["specifier-cast-mismatch"]
+        );
+        assert_eq!(
+            typecast(r#"printf("%-10.2Lf %LG", x, y);"#, &config),
+            r#"printf("%-10.2Lf %LG", (long double) (x), (long double) (y));"#
+        );
+    }
+
+    /// Returns the offsets of the `*` widths and precisions that
+    /// `display_optimize` can't pass on.
+    fn unoptimizable(source: &str) -> Vec<usize> {
+        IntermediateRepresentation::parse(source, &Config::default())
+            .expect("source code is valid")
+            .unoptimizable()
+            .into_iter()
+            .map(|error| match error {
+                Error::UnoptimizableStar(span) => span.start,
+                error => panic!("unexpected error: {error:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn optimize_refuses_star_args() {
+        assert_eq!(unoptimizable(r#"printf("%*d", w, x);"#), [9]);
+        assert_eq!(unoptimizable(r#"printf("%-*.*f", w, p, x);"#), [10, 12]);
+        assert_eq!(unoptimizable(r#"printf("%1$*2$d", x, w);"#), [11]);
+        assert!(unoptimizable(r#"printf("%5.2f %d", x, y);"#).is_empty());
+    }
+
+    #[test]
+    fn star_width_and_precision() {
+        let config = Config::default();
+        assert_eq!(
+            typecast(r#"printf("%*d|%.*f|%*.*f", w, x, p, y, w, p, z);"#, &config),
+            r#"printf("%*d|%.*f|%*.*f", (int) (w), (int) (x), (int) (p), (float) (y), (int) (w), (int) (p), (float) (z));"#
+        );
+        assert_eq!(
+            errors(r#"printf("%*d", w);"#, &config),
+            ["excess-specifiers"]
+        );
+        assert_eq!(
+            warnings(r#"printf("%*d", w, x, y);"#, &config),
+            ["excess-args"]
+        );
+    }
+
+    #[test]
+    fn star_args_are_counted_and_checked() {
+        let config = Config::default();
+        assert!(errors(r#"printf("%*.*f", w, p, x);"#, &config).is_empty());
+        assert_eq!(
+            errors(r#"printf("%*.*f", w, p);"#, &config),
+            ["excess-specifiers"]
+        );
+        assert_eq!(
+            warnings(r#"printf("%*.*f", w, p, x, y);"#, &config),
+            ["excess-args"]
+        );
+        assert_eq!(
+            errors(r#"printf("%*.*f", (double) w, p, x);"#, &config),
+            ["star-arg-mismatch"]
+        );
+    }
+
+    #[test]
+    fn fprintf_passes_stream_through() {
+        let source = r#"fprintf(stderr, "%d\n", x);"#;
+        assert_eq!(
+            typecast(source, &Config::default()),
+            r#"fprintf((FILE*) (stderr), "%d\n", (int) (x));"#
+        );
+
+        let ir = IntermediateRepresentation::parse(source, &Config::default()).unwrap();
+        assert_eq!(ir.display_modernize().to_string(), source);
+        assert_eq!(
+            ir.display_optimize().to_string(),
+            r#"safe_fprintf((FILE*) (stderr), 4, "", (void*) &(x), fmt_int, "\n");"#
+        );
+    }
+
+    #[test]
+    fn fprintf_validates_like_printf() {
+        let config = Config::default();
+        for (printf, fprintf) in [
+            (r#"printf("%d");"#, r#"fprintf(stderr, "%d");"#),
+            (r#"printf("%s", 5);"#, r#"fprintf(stderr, "%s", 5);"#),
+            (r#"printf(fmt, x);"#, r#"fprintf(stderr, fmt, x);"#),
+            (
+                r#"printf("%d %d", x, y);"#,
+                r#"fprintf(stderr, "%d %d", x, y);"#,
+            ),
+        ] {
+            assert_eq!(
+                errors(printf, &config),
+                errors(fprintf, &config),
+                "{fprintf}"
+            );
+        }
+        assert!(errors(r#"myfprintf(stderr, "%d");"#, &config).is_empty());
+    }
+
+    #[test]
+    fn fprintf_stream_is_not_checked() {
+        let config = Config {
+            check_buffers: true,
+            ..Config::default()
+        };
+        assert!(errors(r#"fprintf(2, "%d", x);"#, &config).is_empty());
+    }
+
+    #[test]
+    fn positional_args_pair_by_index() {
+        let config = Config::default();
+        assert_eq!(
+            typecast(r#"printf("%2$s is %1$d", age, name);"#, &config),
+            r#"printf("%2$s is %1$d", (int) (age), (char*) (name));"#
+        );
+        // an argument can be referenced more than once, and is cast once
+        assert_eq!(
+            typecast(r#"printf("%1$d %1$d", x);"#, &config),
+            r#"printf("%1$d %1$d", (int) (x));"#
+        );
+        assert_eq!(
+            errors(r#"printf("%2$s %1$d", 42, 7);"#, &config),
+            ["specifier-arg-mismatch"]
+        );
+    }
+
+    #[test]
+    fn positional_args_are_not_mixed() {
+        let config = Config::default();
+        assert_eq!(
+            errors(r#"printf("%1$d %d", x, y);"#, &config),
+            ["mixed-positions"]
+        );
+        assert_eq!(
+            errors(r#"printf("%s %2$d", s, x);"#, &config),
+            ["mixed-positions"]
+        );
+    }
+
+    #[test]
+    fn positional_args_must_exist() {
+        let config = Config::default();
+        assert_eq!(
+            errors(r#"printf("%1$d %3$d", x, y);"#, &config),
+            ["missing-position"]
+        );
+        assert_eq!(
+            errors(r#"printf("%0$d", x);"#, &config),
+            ["missing-position"]
+        );
+        assert_eq!(
+            warnings(r#"printf("%2$d", x, y);"#, &config),
+            ["excess-args"]
+        );
+    }
+
+    #[test]
+    fn positional_star_pairs_by_index() {
+        let config = Config::default();
+        assert_eq!(
+            typecast(r#"printf("%1$*2$d", x, w);"#, &config),
+            r#"printf("%1$*2$d", (int) (x), (int) (w));"#
+        );
+        assert_eq!(
+            typecast(r#"printf("%2$-*1$.*3$f", w, x, p);"#, &config),
+            r#"printf("%2$-*1$.*3$f", (int) (w), (float) (x), (int) (p));"#
+        );
+        // the same argument can be the width of several specifiers
+        assert_eq!(
+            typecast(r#"printf("%2$*1$d %3$*1$s", w, x, s);"#, &config),
+            r#"printf("%2$*1$d %3$*1$s", (int) (w), (int) (x), (char*) (s));"#
+        );
+    }
+
+    #[test]
+    fn positional_star_must_be_int() {
+        let config = Config::default();
+        assert_eq!(
+            errors(r#"printf("%1$*2$d", x, 2.5);"#, &config),
+            ["star-arg-mismatch"]
+        );
+        assert_eq!(
+            errors(r#"printf("%1$*2$d", x, (char*) w);"#, &config),
+            ["star-arg-mismatch"]
+        );
+        assert!(errors(r#"printf("%1$*2$d", x, (short) w);"#, &config).is_empty());
+    }
+
+    #[test]
+    fn positional_star_must_exist() {
+        let config = Config::default();
+        assert_eq!(
+            errors(r#"printf("%1$*3$d", x, w);"#, &config),
+            ["missing-position"]
+        );
+        assert_eq!(
+            errors(r#"printf("%1$*2$d", x);"#, &config),
+            ["missing-position"]
+        );
+    }
+
+    #[test]
+    fn positional_star_is_referenced() {
+        let config = Config::default();
+        assert!(errors(r#"printf("%1$.*2$s", s, 3);"#, &config).is_empty());
+        assert_eq!(
+            warnings(r#"printf("%1$d", x, w);"#, &config),
+            ["excess-args"]
+        );
+    }
+
+    #[test]
+    fn positional_star_is_not_mixed() {
+        let config = Config::default();
+        assert_eq!(
+            errors(r#"printf("%*2$d", x, w);"#, &config),
+            ["mixed-positions"]
+        );
+        assert_eq!(
+            errors(r#"printf("%1$*d", x, w);"#, &config),
+            ["mixed-positions"]
+        );
+    }
+
+    #[test]
+    fn dprintf_keeps_fd() {
+        let config = Config::default();
+        assert_eq!(
+            typecast(r#"dprintf(fds[i] + 1, "%s\n", s);"#, &config),
+            r#"dprintf((int) (fds[i] + 1), "%s\n", (char*) (s));"#
+        );
+        assert_eq!(
+            errors(r#"dprintf(fd, "%d\n");"#, &config),
+            ["excess-specifiers"]
+        );
+    }
+
+    #[test]
+    fn asprintf_keeps_out_pointer() {
+        let config = Config::default();
+        assert_eq!(
+            typecast(r#"asprintf(& bufs[1], "%d\n", x);"#, &config),
+            r#"asprintf((char**) (& bufs[1]), "%d\n", (int) (x));"#
+        );
+        assert_eq!(
+            errors(r#"asprintf(&buf, "%s\n");"#, &config),
+            ["excess-specifiers"]
+        );
+    }
+
+    #[test]
+    fn printf_must_be_a_whole_identifier() {
+        let source = "myprintf(x);\nprintfoo(x);\nstruct { int printf; } s;\n\
+                      log.printf(\"%d\");\nlog->printf(\"%d\");\ns.printf = 1;\n";
+        let ir = IntermediateRepresentation::parse(source, &Config::default())
+            .expect("source code is valid");
+        assert_eq!(ir.sites().count(), 0);
+        assert_eq!(ir.display_typecast().to_string(), source);
+    }
+
+    #[test]
+    fn comments_before_paren() {
+        let config = Config::default();
+        assert_eq!(
+            errors("printf /* log */ (\"%d\");", &config),
+            ["excess-specifiers"]
+        );
+        assert_eq!(
+            errors("printf // log\n    (\"%d\");", &config),
+            ["excess-specifiers"]
+        );
+        // anything else between the name and the paren isn't a call
+        let source = "printf /* log */ = (\"%d\");";
+        let ir = IntermediateRepresentation::parse(source, &config).expect("source code is valid");
+        assert_eq!(ir.sites().count(), 0);
+    }
+
+    #[test]
+    fn asprintf_out_pointers() {
+        let source = r#"asprintf(&msg, "%s=%d", key, val);"#;
+        let typecasted = r#"asprintf((char**) (&msg), "%s=%d", (char*) (key), (int) (val));"#;
+        assert_eq!(typecast(source, &Config::default()), typecasted);
+
+        let config = Config {
+            check_buffers: true,
+            ..Config::default()
+        };
+        assert!(errors(source, &config).is_empty());
+        assert!(errors(typecasted, &config).is_empty());
+        assert_eq!(
+            errors(r#"asprintf(0, "%d", x);"#, &config),
+            ["non-pointer-out"]
+        );
+    }
+
+    #[test]
+    fn invalid_escapes_are_reported() {
+        let config = Config::default();
+        assert_eq!(errors(r#"printf("\q");"#, &config), ["invalid-escape"]);
+        assert_eq!(errors(r#"printf("%d\x", x);"#, &config), ["invalid-escape"]);
+        assert!(errors(r#"printf("%d\n\t\123\xFF", x);"#, &config).is_empty());
+        // raw strings don't have escapes
+        let config = Config {
+            cpp: true,
+            ..Config::default()
+        };
+        assert!(errors(r#"printf(R"(\q)");"#, &config).is_empty());
+    }
+
+    #[test]
+    fn typecast_is_idempotent_for_pre_args() {
+        let config = Config::default();
+        let typecasted = typecast(r#"dprintf(fd, "%d\n", x);"#, &config);
+        assert_eq!(typecasted, r#"dprintf((int) (fd), "%d\n", (int) (x));"#);
+        assert_eq!(typecast(&typecasted, &config), typecasted);
+
+        let typecasted = typecast(r#"snprintf(buf, n, "%s", s);"#, &config);
+        assert_eq!(typecast(&typecasted, &config), typecasted);
+    }
+
+    #[test]
+    fn concatenated_literals() {
+        let config = Config::default();
+        assert_eq!(
+            typecast(r#"printf("Hello " "%s", name);"#, &config),
+            r#"printf("Hello " "%s", (char*) (name));"#
+        );
+        assert_eq!(
+            typecast(r#"printf("Hello " "%s" "\n", name);"#, &config),
+            r#"printf("Hello " "%s" "\n", (char*) (name));"#
+        );
+        assert_eq!(
+            errors(r#"printf("%" "d", x);"#, &config),
+            ["split-specifier"]
+        );
+        assert_eq!(
+            errors(r#"printf("a" "%.2" "f" "\n", x);"#, &config),
+            ["split-specifier"]
+        );
+        // concatenates to a `% d` specifier, rather than a literal `%`
+        assert_eq!(
+            errors(r#"printf("100%" " done\n");"#, &config),
+            ["split-specifier"]
+        );
+    }
+
+    #[test]
+    fn empty_arguments() {
+        let config = Config::default();
+        for source in [
+            r#"printf("%d", x,);"#,
+            r#"printf("%d",, x);"#,
+            r#"printf(, "%d", x);"#,
+        ] {
+            assert_eq!(errors(source, &config), ["empty-argument"], "{source}");
+        }
+
+        // the call after a trailing comma is still validated
+        let source = r#"printf("%d", x,); printf("%s\n", 5);"#;
+        let errors = IntermediateRepresentation::parse(source, &config)
+            .expect_err("empty argument is an error");
+        let rules: Vec<_> = errors.iter().map(|error| error.error().rule()).collect();
+        assert_eq!(rules, ["empty-argument", "specifier-arg-mismatch"]);
+        assert_eq!(errors[0].offset(), source.find(",)").unwrap());
+
+        // calls without empty arguments are unaffected
+        assert_eq!(
+            typecast(r#"printf("%d %d\n", x, y);"#, &config),
+            r#"printf("%d %d\n", (int) (x), (int) (y));"#
+        );
+    }
+
+    #[test]
+    fn macro_format_strings() {
+        let config = Config::default();
+        let source = "#define FMT \"x = %d\\n\"\nprintf(FMT, x);\n";
+        // the `#define` can't be rewritten, so the name is kept
+        assert_eq!(
+            typecast(source, &config),
+            "#define FMT \"x = %d\\n\"\nprintf(FMT, (int) (x));\n"
+        );
+        let ir = IntermediateRepresentation::parse(source, &config).unwrap();
+        assert_eq!(ir.deps(), [("FMT", "\"x = %d\\n\"")]);
+        assert_eq!(
+            ir.display_optimize().to_string(),
+            "#define FMT \"x = %d\\n\"\nsafe_printf(4, \"x = \", (void*) &(x), fmt_int, \"\\n\");\n"
+        );
+
+        // errors point into the `#define`
+        let source = "#define FMT \"%s\\n\"\nprintf(FMT, 5);\n";
+        let reported = IntermediateRepresentation::parse(source, &config).unwrap_err();
+        assert_eq!(reported[0].error().rule(), "specifier-arg-mismatch");
+        assert_eq!(reported[0].offset(), source.find('%').unwrap());
+
+        // only macros that are exactly one string literal
+        for source in [
+            "#define FMT(x) \"%d\"\nprintf(FMT(1), x);",
+            "#define FMT get_format()\nprintf(FMT, x);",
+            "#define FMT \"%d\" + 1\nprintf(FMT, x);",
+            "printf(FMT, x);",
+        ] {
+            assert_eq!(errors(source, &config), ["nonliteral-format"], "{source}");
+        }
+    }
+
+    #[test]
+    fn flags_are_kept() {
+        let config = Config::default();
+        assert_eq!(
+            typecast(r#"printf("%#08x", v);"#, &config),
+            r#"printf("%#08x", (int) (v));"#
+        );
+        assert_eq!(
+            typecast(r#"printf("% d|%-+5d|%0#o", x, y, z);"#, &config),
+            r#"printf("% d|%-+5d|%0#o", (int) (x), (int) (y), (int) (z));"#
+        );
+        // one specifier, so one argument short
+        assert_eq!(
+            errors(r#"printf("%#08x");"#, &config),
+            ["excess-specifiers"]
+        );
+        assert_eq!(
+            errors(r#"printf("100% done");"#, &config),
+            ["excess-specifiers"]
+        );
+    }
+
+    #[test]
+    fn errors_are_collected_past_failed_checks() {
+        let config = Config {
+            check_buffers: true,
+            ..Config::default()
+        };
+        // pre-arguments are checked even if the format string isn't a literal
+        assert_eq!(
+            errors(r#"sprintf(5, fmt, x);"#, &config),
+            ["non-pointer-buffer", "nonliteral-format"]
+        );
+        // escapes are checked even if specifiers can't be paired
+        assert_eq!(
+            errors(r#"printf("\q %y %d", x);"#, &config),
+            ["invalid-escape", "unknown-conversion"]
+        );
+        // and every call is still checked, in source order
+        let mut config = Config::default();
+        config.rules.set("excess-args", Level::Deny).unwrap();
+        assert_eq!(
+            errors(
+                r#"printf("%d", x, y); printf("%s", 5); printf("%d");"#,
+                &config
+            ),
+            ["excess-args", "specifier-arg-mismatch", "excess-specifiers"]
+        );
+    }
+
+    #[test]
+    fn empty_format_with_args() {
+        let config = Config::default();
+        assert!(warnings(r#"printf("");"#, &config).is_empty());
+        assert_eq!(
+            warnings(r#"printf("", x, y);"#, &config),
+            ["empty-format-with-args"]
+        );
+        assert_eq!(
+            warnings(r#"sprintf(buf, "", x);"#, &config),
+            ["empty-format-with-args"]
+        );
+        // the arguments are passed through as is, like other excess arguments
+        assert_eq!(
+            typecast(r#"printf("", x, y);"#, &config),
+            r#"printf("", x, y);"#
+        );
+
+        let source = r#"printf("", x, f(y));"#;
+        let warnings = IntermediateRepresentation::parse(source, &config)
+            .unwrap()
+            .into_warnings();
+        let labels: Vec<_> = warnings[0]
+            .labels()
+            .unwrap()
+            .map(|label| &source[label.offset()..label.offset() + label.len()])
+            .collect();
+        assert_eq!(labels, [r#""""#, "x, f(y)"]);
+    }
+
+    #[test]
+    fn commas_inside_brackets_and_braces() {
+        let config = Config::default();
+        assert_eq!(
+            typecast(r#"printf("%d %d", a[i, j], (int[]){1, 2}[0]);"#, &config),
+            r#"printf("%d %d", (int) (a[i, j]), (int) ((int[]){1, 2}[0]));"#
+        );
+        assert_eq!(
+            typecast(r#"printf("%d", ((struct p){.x = 1, .y = 2}).x);"#, &config),
+            r#"printf("%d", (int) (((struct p){.x = 1, .y = 2}).x));"#
+        );
+        assert_eq!(
+            typecast(r#"printf("%d", a<:i:>);"#, &config),
+            r#"printf("%d", (int) (a<:i:>));"#
+        );
+        assert_eq!(
+            warnings(r#"printf("%d", (int[]){1, 2}[0], x);"#, &config),
+            ["excess-args"]
+        );
+    }
+
+    #[test]
+    fn buffer_size_counts_concatenated_literal_text() {
+        let config = Config::default();
+        // each literal's specifiers are paired in order
+        assert_eq!(
+            typecast(r#"printf("%d " "and" " %s\n", x, s);"#, &config),
+            r#"printf("%d " "and" " %s\n", (int) (x), (char*) (s));"#
+        );
+        // 6 characters and the nul terminator, not the quotes between them
+        assert!(warnings(r#"snprintf(buf, 7, "abc"  "def");"#, &config).is_empty());
+        assert_eq!(
+            warnings(r#"snprintf(buf, 6, "abc" "d" "ef");"#, &config),
+            ["buffer-too-small"]
+        );
+        // `é` is 2 bytes in UTF-8
+        assert!(warnings(r#"snprintf(buf, 4, "é" "\n");"#, &config).is_empty());
+        assert_eq!(
+            warnings(r#"snprintf(buf, 3, "é" "\n");"#, &config),
+            ["buffer-too-small"]
+        );
+    }
+
+    #[test]
+    fn indexed_compound_literals_are_not_casts() {
+        let config = Config::default();
+        assert!(errors(r#"printf("%c", (char*){"a"}[0]);"#, &config).is_empty());
+        assert_eq!(
+            typecast(r#"printf("%c", (char*){"a"}[0]);"#, &config),
+            r#"printf("%c", (char) ((char*){"a"}[0]));"#
+        );
+        assert_eq!(
+            typecast(r#"printf("%d", (int*){p}<:1:>);"#, &config),
+            r#"printf("%d", (int) ((int*){p}<:1:>));"#
+        );
+        // without an index, the compound literal's type is still its cast
+        assert_eq!(
+            errors(r#"printf("%d", (char*){"a"});"#, &config),
+            ["specifier-cast-mismatch"]
+        );
+        assert!(errors(
+            r#"printf("%d %s", f(a, (int){5}), (*fp)(a, (int[]){1, 2}[1]));"#,
+            &config
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn disabled_blocks_are_skipped() {
+        let source = "#if 0\n\
+                      printf(\"%d\");\n\
+                      #ifdef DEBUG\n\
+                      printf(\"%s\");\n\
+                      #endif\n\
+                      printf(\"%d\");\n\
+                      #else\n\
+                      printf(\"%d\", x);\n\
+                      #endif\n\
+                      #if 0 /* disabled */\n\
+                      #if 0\n\
+                      printf(\"%d\");\n\
+                      #endif\n\
+                      #elif 0\n\
+                      printf(\"%d\");\n\
+                      #endif\n\
+                      printf(\"%s\", s);\n";
+        let ir = IntermediateRepresentation::parse(source, &Config::default())
+            .expect("source code is valid");
+        let lines: Vec<usize> = ir.sites().map(|site| site.location().line).collect();
+        assert_eq!(lines, [8, 17]);
+
+        // an unclosed block runs to the end of the file
+        assert!(errors("#if 0\nprintf(\"%d\");\n", &Config::default()).is_empty());
+        assert_eq!(
+            errors("#if FALSE\nprintf(\"%d\");\n#endif\n", &Config::default()),
+            ["excess-specifiers"]
+        );
+    }
+
+    #[test]
+    fn wide_strings_and_chars() {
+        let config = Config::default();
+        let source = r#"wprintf(L"%ls: %lc\n", name, c);"#;
+        let typecasted = r#"wprintf(L"%ls: %lc\n", (wchar_t*) (name), (wint_t) (c));"#;
+        assert_eq!(typecast(source, &config), typecasted);
+        assert_eq!(typecast(typecasted, &config), typecasted);
+        assert_eq!(
+            errors(r#"wprintf(L"%ls", (char*) s);"#, &config),
+            ["specifier-cast-mismatch"]
+        );
+        assert_eq!(
+            IntermediateRepresentation::parse(r#"wprintf(L"%ls\n", s);"#, &config)
+                .expect("source code is valid")
+                .display_optimize()
+                .to_string(),
+            r#"safe_wprintf(4, L"", (void*) (s), fmt_wide_string, L"\n");"#
+        );
+    }
+
+    #[test]
+    fn dynamic_width_wide_char() {
+        assert!(errors(
+            r#"wprintf(L"%*lc", (int) w, (wint_t) wc);"#,
+            &Config::default()
+        )
+        .is_empty());
+        assert_eq!(
+            typecast(r#"wprintf(L"%*lc", w, wc);"#, &Config::default()),
+            r#"wprintf(L"%*lc", (int) (w), (wint_t) (wc));"#
+        );
+        assert_eq!(
+            errors(
+                r#"wprintf(L"%*lc", (char*) w, (wint_t) wc);"#,
+                &Config::default()
+            ),
+            ["star-arg-mismatch"]
+        );
+    }
+
+    #[test]
+    fn cast_matching_specifier_is_pedantic() {
+        let source = r#"printf("%d %ld %s", (int) x, (long) y, (char*) s);"#;
+        assert!(warnings(source, &Config::default()).is_empty());
+
+        let config = Config {
+            pedantic: true,
+            ..Config::default()
+        };
+        assert_eq!(
+            warnings(source, &config),
+            [
+                "cast-matches-specifier",
+                "cast-matches-specifier",
+                "cast-matches-specifier"
+            ]
+        );
+        assert!(warnings(r#"printf("%d", (short) x);"#, &config).is_empty());
+
+        // the source code is still valid and can be rewritten
+        let ir = IntermediateRepresentation::parse(source, &config).expect("source code is valid");
+        assert_eq!(ir.warnings().len(), 3);
+        assert_eq!(ir.display_typecast().to_string(), source);
+
+        // a cast that's redundant for the argument is only reported once
+        let config = Config {
+            pedantic: true,
+            warn_redundant_casts: true,
+            ..Config::default()
+        };
+        assert_eq!(
+            warnings(r#"printf("%d", (int) 5);"#, &config),
+            ["redundant-cast"]
+        );
     }
 }
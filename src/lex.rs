@@ -1,6 +1,6 @@
 use crate::ir::CType;
 use crate::parse::Specifier;
-use logos::Logos;
+use logos::{Lexer, Logos};
 
 #[derive(Debug, Clone, Copy, Logos, PartialEq, Eq)]
 #[logos(subpattern l = "[a-zA-Z_$]")]
@@ -11,19 +11,33 @@ use logos::Logos;
 #[logos(subpattern sp = r"u8|(?&cp)")]
 // white space
 #[logos(subpattern ws = r"[ \t\v\r\n\f]")]
-// escape sequence
-#[logos(subpattern es = r#"[\\](['"%?\\abefnrtv]|[0-7]+|[xu][a-fA-F0-9]+|[\r]?[\n])"#)]
+// escape sequence, or a backslash meant as one, since format strings validate them
+#[logos(subpattern es = r#"[\\]([^\r\n]|[\r]?[\n])"#)]
+// raw string delimiter
+#[logos(subpattern rd = r#"[^ ()\\\t\v\f\r\n"]*"#)]
 pub enum SourceToken<'src> {
     #[regex("//[^\r\n]*")]
     #[token("/*", |lex| {
         lex.bump(lex.remainder().find("*/")? + 2);
-        None
+        Some(())
     })]
     Comment,
 
+    // macro bodies are templates rather than calls, so they're skipped whole,
+    // including lines continued with a backslash
+    #[regex(r"#[ \t]*define([^\\\r\n]|[\\][^\r\n]|[\\]\r?\n)*")]
+    Define,
+
+    // code that's compiled out, like an `#if 0` block up to its `#else` or `#endif`
+    #[regex(r"#[ \t]*(el)?if[ \t]+0", disabled_block)]
+    Disabled,
+
     #[regex(r#"((?&sp)?"([^"\\\n]|(?&es))*"(?&ws)*)+"#)]
     String,
 
+    #[regex(r#"(?&sp)?R"(?&rd)\("#, |lex| raw_string(lex).is_some())]
+    RawString,
+
     #[token("(")]
     LParen,
 
@@ -33,6 +47,11 @@ pub enum SourceToken<'src> {
     #[regex("(?&l)(?&a)*")]
     Identifier(&'src str),
 
+    // member access, so calls like `log->printf(...)` aren't mistaken for `printf`
+    #[token(".")]
+    #[token("->")]
+    Member,
+
     #[regex(r"(?&ws)+", logos::skip)]
     Whitespace,
 
@@ -40,7 +59,7 @@ pub enum SourceToken<'src> {
     Other,
 }
 
-#[derive(Debug, Logos)]
+#[derive(Debug, Clone, Copy, Logos, PartialEq, Eq)]
 // octal digit
 #[logos(subpattern o = "[0-7]")]
 // decimal digit
@@ -61,7 +80,7 @@ pub enum SourceToken<'src> {
 // float suffix
 #[logos(subpattern fs = "[fFlL]")]
 // integer suffix
-#[logos(subpattern is = "([uU]([lL]|ll|LL)?)|(([lL]|ll|LL)[uU]?)")]
+#[logos(subpattern is = "([uU]([lL]|ll|LL|[zZ])?)|(([lL]|ll|LL|[zZ])[uU]?)")]
 #[logos(subpattern l = "[a-zA-Z_$]")]
 #[logos(subpattern a = "[a-zA-Z_$0-9]")]
 // char prefix
@@ -70,22 +89,32 @@ pub enum SourceToken<'src> {
 #[logos(subpattern sp = r"u8|(?&cp)")]
 // white space
 #[logos(subpattern ws = r"[ \t\v\r\n\f]")]
-// escape sequence
-#[logos(subpattern es = r#"[\\](['"%?\\abefnrtv]|[0-7]+|[xu][a-fA-F0-9]+|[\r]?[\n])"#)]
+// escape sequence, or a backslash meant as one, since format strings validate them
+#[logos(subpattern es = r#"[\\]([^\r\n]|[\r]?[\n])"#)]
+// raw string delimiter
+#[logos(subpattern rd = r#"[^ ()\\\t\v\f\r\n"]*"#)]
 pub enum ArgToken<'src> {
     #[regex("//[^\r\n]*")]
     #[token("/*", |lex| {
         lex.bump(lex.remainder().find("*/")? + 2);
-        None
+        Some(())
     })]
     Comment,
 
     #[regex(r"\.\.\.")]
     #[regex(r">>=|<<=|[+]=|-=|[*]=|/=|%=|&=|[\^]=|\|=")]
-    #[regex(r">>|<<|[+][+]|--|->|&&|[|][|]|<=|>=|==|!=|<%|%>|<:|:>")]
-    #[regex(r"[;{}:=\[\].&!~\-+*/%<>^|?\\#]")]
+    #[regex(r">>|<<|[+][+]|--|->|&&|[|][|]|<=|>=|==|!=")]
+    #[regex(r"[;:=.&!~\-+*/%<>^|?\\#]")]
     Symbol,
 
+    // `[` or `{`, or their digraphs
+    #[regex(r"[\[{]|<:|<%")]
+    LBracket,
+
+    // `]` or `}`, or their digraphs
+    #[regex(r"[\]}]|:>|%>")]
+    RBracket,
+
     #[token("(")]
     LParen,
 
@@ -101,6 +130,9 @@ pub enum ArgToken<'src> {
     #[regex(r#"((?&sp)?"([^"\\\n]|(?&es))*"(?&ws)*)+"#, |lex| trim(lex.slice()))]
     String(&'src str),
 
+    #[regex(r#"(?&sp)?R"(?&rd)\("#, raw_string)]
+    RawString(&'src str),
+
     #[regex("((?&hp)(?&h)+|(?&bp)(?&b)+|(?&nz)(?&d)*|0(?&o)*)(?&is)?")]
     Int,
 
@@ -110,12 +142,32 @@ pub enum ArgToken<'src> {
     #[token("(int)", |_| CType::Int)]
     #[token("(float)", |_| CType::Float)]
     #[token("(char*)", |_| CType::String)]
+    #[token("(wint_t)", |_| CType::WideChar)]
+    #[token("(wchar_t*)", |_| CType::WideString)]
+    #[token("(char)", |_| CType::Char)]
+    #[token("(short)", |_| CType::Short)]
+    #[token("(long)", |_| CType::Long)]
+    #[token("(long long)", |_| CType::LongLong)]
+    #[token("(long double)", |_| CType::LongDouble)]
+    #[token("(intmax_t)", |_| CType::IntMax)]
+    #[token("(size_t)", |_| CType::Size)]
+    #[token("(ptrdiff_t)", |_| CType::PtrDiff)]
+    #[token("(double)", |_| CType::Double)]
+    #[token("(void*)", |_| CType::Pointer)]
+    #[token("(void *)", |_| CType::Pointer)]
+    // e.g. `(unsigned)` or `( unsigned int )`
+    #[regex(r"\((?&ws)*unsigned(?&ws)*\)", |_| CType::Unsigned)]
+    #[regex(r"\((?&ws)*unsigned(?&ws)+int(?&ws)*\)", |_| CType::Unsigned)]
+    #[regex(r"\((?&ws)*unsigned(?&ws)+long((?&ws)+int)?(?&ws)*\)", |_| CType::UnsignedLong)]
+    #[regex(r"\((?&ws)*unsigned(?&ws)+long(?&ws)+long((?&ws)+int)?(?&ws)*\)", |_| CType::UnsignedLongLong)]
     TypeCast(CType),
 
     #[regex("(?&l)(?&a)*")]
     Identifier(&'src str),
 
     #[regex(r"(?&ws)+", logos::skip)]
+    // a backslash continuing a line, like in a `#define` body
+    #[regex(r"[\\]\r?\n", logos::skip)]
     Whitespace,
 
     #[error]
@@ -123,19 +175,140 @@ pub enum ArgToken<'src> {
 }
 
 #[derive(Debug, Logos)]
-#[logos(subpattern opts = r"[+-]?([0-9]+([.][0-9]*)?|[.][0-9]+)")]
+// position, flags, width, and precision, where the width and precision can
+// come from arguments in order like `*` or by position like `*2$`
+#[logos(subpattern opts = r"([0-9]+[$])?[-+ #0]*([0-9]*|[*]([0-9]+[$])?)([.]([0-9]*|[*]([0-9]+[$])?))?")]
 pub enum FormatToken<'src> {
-    #[regex(r"%(?&opts)?[di]", |lex| Specifier::new(trim(lex.slice()), CType::Int))]
-    #[regex(r"%(?&opts)?s", |lex| Specifier::new(trim(lex.slice()), CType::String))]
-    #[regex(r"%(?&opts)?f", |lex| Specifier::new(trim(lex.slice()), CType::Float))]
+    #[regex(r"%(?&opts)[di]", |lex| Specifier::new(lex.slice(), CType::Int))]
+    #[regex(r"%(?&opts)D", |lex| Specifier::new(lex.slice(), CType::Long))]
+    #[regex(r"%(?&opts)(hh|h|ll|l|j|z|t)[diouxXbB]", modified_integer)]
+    #[regex(r"%(?&opts)u", |lex| Specifier::new(lex.slice(), CType::Unsigned))]
+    #[regex(r"%(?&opts)[xXo]", |lex| Specifier::new(lex.slice(), CType::Int))]
+    // binary, only recognized with `--c-standard c23`
+    #[regex(r"%(?&opts)[bB]", |lex| Specifier::new(lex.slice(), CType::Int))]
+    #[regex(r"%(?&opts)c", |lex| Specifier::new(lex.slice(), CType::Char))]
+    #[regex(r"%(?&opts)s", |lex| Specifier::new(lex.slice(), CType::String))]
+    #[regex(r"%(?&opts)lc", |lex| Specifier::new(lex.slice(), CType::WideChar))]
+    #[regex(r"%(?&opts)ls", |lex| Specifier::new(lex.slice(), CType::WideString))]
+    #[regex(r"%(?&opts)l?[fFeEgGaA]", |lex| Specifier::new(lex.slice(), CType::Float))]
+    #[regex(r"%(?&opts)L[fFeEgGaA]", |lex| Specifier::new(lex.slice(), CType::LongDouble))]
+    #[regex(r"%(?&opts)p", |lex| Specifier::new(lex.slice(), CType::Pointer))]
+    // Microsoft's, only recognized with `--ms-compat`
+    #[regex(r"%(?&opts)C", |lex| Specifier::new(lex.slice(), CType::WideChar))]
+    #[regex(r"%(?&opts)S", |lex| Specifier::new(lex.slice(), CType::WideString))]
     Specifier(Specifier<'src>),
 
+    // writes the number of characters printed so far through a pointer
+    #[regex(r"%(?&opts)(hh|h|ll|l|j|z|t)?n")]
+    WriteBack,
+
+    // anything else that looks like a specifier, including its length modifier, e.g. `%lc`
+    #[regex(r"%(?&opts)(hh|h|ll|l|j|z|t|L)?[a-zA-Z]", priority = 1)]
+    // a percent sign that doesn't start anything, e.g. in `"100%"`
+    #[token("%", priority = 0)]
+    Unknown,
+
     #[error]
-    #[regex("\\\\.")]
+    // escape sequences are consumed whole, e.g. the `\033` in `"\033[31m%d"`
+    #[regex(r"\\([0-7][0-7]?[0-7]?|x[a-fA-F0-9]+|.)")]
+    // a literal percent sign, which doesn't start a specifier
+    #[token("%%")]
     Normal,
 }
 
-/// Trim first and last byte from a string
+/// Returns the next token that isn't a comment, since comments can go
+/// between the tokens of a call, like `printf /* log */ ("%d", x)`.
+pub fn next_uncommented<'src>(
+    lex: &mut Lexer<'src, SourceToken<'src>>,
+) -> Option<SourceToken<'src>> {
+    lex.find(|token| *token != SourceToken::Comment)
+}
+
+/// Consumes the rest of a block after an `#if 0` or `#elif 0`, up to the
+/// `#else` or `#elif` that could enable code again, or through the `#endif`
+/// that ends it, skipping nested conditionals. Returns `false` if the
+/// condition isn't just `0`, like in `#if 0x10`.
+fn disabled_block<'src>(lex: &mut Lexer<'src, SourceToken<'src>>) -> bool {
+    let remainder = lex.remainder();
+    let condition = remainder.split(['\r', '\n']).next().unwrap_or("").trim();
+    if !(condition.is_empty() || condition.starts_with("//") || condition.starts_with("/*")) {
+        return false;
+    }
+
+    let mut depth = 0usize;
+    let mut offset = 0;
+    for line in remainder.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        if start == 0 {
+            // the rest of the `#if 0` line itself
+            continue;
+        }
+
+        let Some(directive) = line.trim_start().strip_prefix('#') else {
+            continue;
+        };
+        let directive = directive.trim_start();
+        let name = &directive[..directive
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(directive.len())];
+        match name {
+            "if" | "ifdef" | "ifndef" => depth += 1,
+            "endif" if depth == 0 => {
+                lex.bump(offset);
+                return true;
+            }
+            "endif" => depth -= 1,
+            "else" | "elif" | "elifdef" | "elifndef" if depth == 0 => {
+                lex.bump(start);
+                return true;
+            }
+            _ => {}
+        }
+    }
+
+    // never closed, so the rest of the file is compiled out
+    lex.bump(remainder.len());
+    true
+}
+
+/// Returns an integer specifier with a length modifier, with the C type
+/// that the modifier makes it format, e.g. `long long` for `%lld`.
+fn modified_integer<'src>(lex: &mut Lexer<'src, FormatToken<'src>>) -> Specifier<'src> {
+    let mut specifier = Specifier::new(lex.slice(), CType::Int);
+    let unsigned = specifier.conversion.ends_with('u');
+    specifier.ctype = match specifier.length_modifier() {
+        "hh" => CType::Char,
+        "h" => CType::Short,
+        "l" if unsigned => CType::UnsignedLong,
+        "l" => CType::Long,
+        "ll" if unsigned => CType::UnsignedLongLong,
+        "ll" => CType::LongLong,
+        "j" => CType::IntMax,
+        "z" => CType::Size,
+        "t" => CType::PtrDiff,
+        modifier => unreachable!("the regex only matches length modifiers, not `{modifier}`"),
+    };
+    specifier
+}
+
+/// Consumes the rest of a C++ raw string literal after its opening
+/// `R"delim(`, returning the contents between the delimiters.
+fn raw_string<'src, T: Logos<'src, Source = str>>(lex: &mut Lexer<'src, T>) -> Option<&'src str> {
+    let opening = lex.slice();
+    let delim = &opening[opening.find('"')? + 1..opening.len() - 1];
+    let closing = format!("){delim}\"");
+
+    let remainder = lex.remainder();
+    let len = remainder.find(&closing)?;
+    lex.bump(len + closing.len());
+    Some(&remainder[..len])
+}
+
+/// Returns the contents of a string literal, or of adjacent ones, between
+/// the first and last quote, e.g. `%d` for `u8"%d" `.
 fn trim(s: &str) -> &str {
-    &s[1..s.len() - 1]
+    let start = s.find('"').map_or(0, |quote| quote + 1);
+    let end = s.rfind('"').unwrap_or(s.len());
+    &s[start..end]
 }
@@ -0,0 +1,26 @@
+//! Validate calls to `printf` and family in C source code, and rewrite them
+//! with type casts or optimized calls.
+
+pub mod config;
+pub mod coverage;
+pub mod error;
+pub mod function;
+pub mod ir;
+pub mod lex;
+pub mod lint;
+pub mod location;
+pub mod macros;
+pub mod parse;
+pub mod rules;
+
+pub use config::Config;
+pub use error::{Error, Reported};
+pub use ir::{CType, IntermediateRepresentation};
+
+/// Validates the calls to `printf` and family in C source code with the
+/// default [`Config`], returning the parsed source code or the errors in it.
+///
+/// Use [`IntermediateRepresentation::parse`] to validate with a custom [`Config`].
+pub fn validate(source: &str) -> Result<IntermediateRepresentation<'_>, Vec<Reported>> {
+    IntermediateRepresentation::parse(source, &Config::default())
+}
@@ -0,0 +1,110 @@
+use crate::error::Error;
+use crate::ir::Site;
+use std::fmt;
+
+/// A project-specific check on calls to `printf` and family, e.g. one that
+/// forbids `%f` in logging.
+///
+/// Lints are registered with [`Config::add_lint`](crate::config::Config::add_lint),
+/// and run on each call site that passed the built-in checks. Any errors
+/// they return are reported like the built-in ones, usually as
+/// [`Error::Lint`].
+pub trait Lint: fmt::Debug {
+    /// Returns the names of the rules this lint reports errors under, so
+    /// their level can be set like a built-in rule's.
+    fn rules(&self) -> &[&'static str];
+
+    /// Returns the errors in a call site, or nothing if it's fine.
+    fn check(&self, site: &Site<'_>, source: &str) -> Vec<Error>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::ir::{CType, IntermediateRepresentation};
+    use crate::rules::Level;
+    use miette::{Diagnostic, Severity};
+
+    /// Forbids `%f` in calls to `printf`, which this project only logs with.
+    #[derive(Debug)]
+    struct NoFloatInLogs;
+
+    impl Lint for NoFloatInLogs {
+        fn rules(&self) -> &[&'static str] {
+            &["no-float-in-logs"]
+        }
+
+        fn check(&self, site: &Site<'_>, _source: &str) -> Vec<Error> {
+            let Site::Printf { format, .. } = site else {
+                return Vec::new();
+            };
+            let floats = format
+                .specifiers()
+                .filter(|(specifier, _)| specifier.ctype == CType::Float);
+
+            floats
+                .map(|(specifier, _)| Error::Lint {
+                    span: format.span(),
+                    label: format!("`%{}` formats a float", specifier.conversion),
+                    rule: "no-float-in-logs",
+                    message: "Floats aren't allowed in logs.".to_string(),
+                    help: Some("Log the value as a fixed-point integer.".to_string()),
+                })
+                .collect()
+        }
+    }
+
+    /// Returns the rules and severities of what's reported in source code.
+    fn reported(source: &str, config: &Config) -> Vec<(&'static str, Severity)> {
+        let reported = match IntermediateRepresentation::parse(source, config) {
+            Ok(ir) => ir.into_warnings(),
+            Err(errors) => errors,
+        };
+        reported
+            .iter()
+            .map(|reported| (reported.error().rule(), reported.severity().unwrap()))
+            .collect()
+    }
+
+    const SOURCE: &str = r#"printf("%f", x); sprintf(buf, "%f", x);"#;
+
+    #[test]
+    fn lint_reports_errors() {
+        let mut config = Config::default();
+        config.add_lint(NoFloatInLogs);
+
+        assert_eq!(
+            reported(SOURCE, &config),
+            [("no-float-in-logs", Severity::Error)]
+        );
+        assert!(reported(SOURCE, &Config::default()).is_empty());
+    }
+
+    #[test]
+    fn lint_rules_can_be_set() {
+        let mut config = Config::default();
+        config.add_lint(NoFloatInLogs);
+
+        config.rules.set("no-float-in-logs", Level::Warn).unwrap();
+        assert_eq!(
+            reported(SOURCE, &config),
+            [("no-float-in-logs", Severity::Warning)]
+        );
+        // only a warning, so the call is still rewritten
+        let ir = IntermediateRepresentation::parse(SOURCE, &config).unwrap();
+        assert_eq!(ir.sites().count(), 2);
+
+        config.rules.set("no-float-in-logs", Level::Allow).unwrap();
+        assert!(reported(SOURCE, &config).is_empty());
+    }
+
+    #[test]
+    fn lint_rules_must_be_registered() {
+        let mut config = Config::default();
+        assert!(config.rules.set("no-float-in-logs", Level::Warn).is_err());
+
+        config.add_lint(NoFloatInLogs);
+        assert!(config.rules.set("no-float-in-logs", Level::Warn).is_ok());
+    }
+}
@@ -0,0 +1,94 @@
+use std::ops::Range;
+
+/// A position in source code.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// Byte offset from the start of the source code.
+    pub offset: usize,
+    /// Line number, starting at 1.
+    pub line: usize,
+    /// Column in characters, starting at 1, so a multi-byte character like
+    /// `é` counts once, and a `\r` ending the previous line isn't counted.
+    pub column: usize,
+}
+
+/// Byte offsets of the start of each line in source code, for turning
+/// offsets into [`Location`]s.
+#[derive(Debug)]
+pub struct LineIndex<'src> {
+    source: &'src str,
+    line_starts: Vec<usize>,
+}
+
+impl<'src> LineIndex<'src> {
+    /// Returns a new [`LineIndex`] of the lines in `source`.
+    pub fn new(source: &'src str) -> Self {
+        let line_starts = [0]
+            .into_iter()
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        Self {
+            source,
+            line_starts,
+        }
+    }
+
+    /// Returns the [`Location`] of a byte offset into the source code.
+    pub fn location(&self, offset: usize) -> Location {
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let line_start = self.line_starts[line - 1];
+
+        Location {
+            offset,
+            line,
+            column: self.source[line_start..offset].chars().count() + 1,
+        }
+    }
+
+    /// Returns the [`Location`]s of the start and end of a span of bytes,
+    /// with the end exclusive like the span's.
+    pub fn span(&self, span: Range<usize>) -> (Location, Location) {
+        (self.location(span.start), self.location(span.end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the line and column of a byte offset.
+    fn line_column(source: &str, offset: usize) -> (usize, usize) {
+        let location = LineIndex::new(source).location(offset);
+        (location.line, location.column)
+    }
+
+    #[test]
+    fn lines_and_columns() {
+        let source = "int x;\n\nprintf(\"%d\",\n       x);";
+        assert_eq!(line_column(source, 0), (1, 1));
+        assert_eq!(line_column(source, 6), (1, 7));
+        assert_eq!(line_column(source, 7), (2, 1));
+        assert_eq!(line_column(source, 8), (3, 1));
+        assert_eq!(line_column(source, source.len() - 3), (4, 8));
+        // the end of the source code is still a location
+        assert_eq!(line_column(source, source.len()), (4, 11));
+    }
+
+    #[test]
+    fn crlf_and_multibyte_columns() {
+        let source = "// é\r\nprintf(\"é%d\", x);";
+        assert_eq!(line_column(source, source.find("printf").unwrap()), (2, 1));
+        assert_eq!(line_column(source, source.find('%').unwrap()), (2, 10));
+    }
+
+    #[test]
+    fn spans_across_lines() {
+        let source = "x = \"é\";\r\nprintf(\"%d\",\r\n       x);";
+        let start = source.find("printf").unwrap();
+        let (start, end) = LineIndex::new(source).span(start..source.len());
+        assert_eq!((start.line, start.column), (2, 1));
+        assert_eq!((end.line, end.column), (3, 11));
+        assert_eq!(end.offset, source.len());
+    }
+}
@@ -0,0 +1,262 @@
+use miette::{Context, Diagnostic, IntoDiagnostic, Severity};
+use safe_printf::config::Config;
+use safe_printf::error::Reported;
+use safe_printf::ir::IntermediateRepresentation;
+use safe_printf::location::LineIndex;
+use serde_json::{json, Value};
+use std::io::{self, BufRead, Write};
+
+/// Runs a minimal language server over stdin and stdout, publishing
+/// diagnostics for documents when they're opened or changed.
+///
+/// Documents are synced in full, and everything else besides `initialize`,
+/// `shutdown`, and `exit` is ignored.
+pub fn serve(config: &Config) -> miette::Result<()> {
+    serve_on(&mut io::stdin().lock(), &mut io::stdout().lock(), config)
+}
+
+/// Runs the language server of [`serve`], reading messages from `stdin` and
+/// writing them to `stdout`.
+fn serve_on(
+    stdin: &mut impl BufRead,
+    stdout: &mut impl Write,
+    config: &Config,
+) -> miette::Result<()> {
+    while let Some(message) = read_message(stdin)? {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+
+        match method {
+            "initialize" => {
+                let capabilities = json!({
+                    "capabilities": { "textDocumentSync": 1 },
+                    "serverInfo": { "name": env!("CARGO_PKG_NAME") },
+                });
+                respond(stdout, &message["id"], capabilities)?;
+            }
+            "shutdown" => respond(stdout, &message["id"], Value::Null)?,
+            "exit" => break,
+            "textDocument/didOpen" => {
+                let document = &params["textDocument"];
+                let text = document["text"].as_str().unwrap_or_default();
+                publish(stdout, &document["uri"], text, config)?;
+            }
+            "textDocument/didChange" => {
+                // full sync, so the last change has the whole text
+                let changes = params["contentChanges"].as_array();
+                if let Some(change) = changes.and_then(|changes| changes.last()) {
+                    let text = change["text"].as_str().unwrap_or_default();
+                    publish(stdout, &params["textDocument"]["uri"], text, config)?;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads a message with a `Content-Length` header, or returns `None` at the end of input.
+fn read_message(input: &mut impl BufRead) -> miette::Result<Option<Value>> {
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        let read = input
+            .read_line(&mut header)
+            .into_diagnostic()
+            .wrap_err("failed reading LSP header")?;
+        if read == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let content_length = content_length
+        .ok_or_else(|| miette::miette!("LSP message is missing a `Content-Length` header"))?;
+    let mut content = vec![0; content_length];
+    input
+        .read_exact(&mut content)
+        .into_diagnostic()
+        .wrap_err("failed reading LSP message")?;
+
+    serde_json::from_slice(&content)
+        .into_diagnostic()
+        .wrap_err("failed parsing LSP message")
+}
+
+fn write_message(output: &mut impl Write, message: Value) -> miette::Result<()> {
+    let content = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{content}", content.len())
+        .and_then(|()| output.flush())
+        .into_diagnostic()
+        .wrap_err("failed writing LSP message")
+}
+
+fn respond(output: &mut impl Write, id: &Value, result: Value) -> miette::Result<()> {
+    write_message(
+        output,
+        json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+    )
+}
+
+/// Validates a document and publishes its diagnostics, including warnings,
+/// which clears them if it's valid.
+fn publish(
+    output: &mut impl Write,
+    uri: &Value,
+    text: &str,
+    config: &Config,
+) -> miette::Result<()> {
+    let errors = match IntermediateRepresentation::parse(text, config) {
+        Ok(repr) => repr.into_warnings(),
+        Err(errors) => errors,
+    };
+    let line_index = LineIndex::new(text);
+    let diagnostics: Vec<Value> = errors
+        .iter()
+        .map(|error| diagnostic(error, text, &line_index))
+        .collect();
+
+    write_message(
+        output,
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        }),
+    )
+}
+
+/// Converts an error to an LSP `Diagnostic`, ranging over its primary label.
+fn diagnostic(error: &Reported, text: &str, line_index: &LineIndex<'_>) -> Value {
+    let (start, end) = error
+        .labels()
+        .and_then(|mut labels| labels.next())
+        .map(|label| (label.offset(), label.offset() + label.len()))
+        .unwrap_or((error.offset(), error.offset()));
+
+    let severity = match error.severity().unwrap_or(Severity::Error) {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+        Severity::Advice => 3,
+    };
+
+    json!({
+        "range": {
+            "start": position(start, text, line_index),
+            "end": position(end, text, line_index),
+        },
+        "severity": severity,
+        "code": error.error().rule(),
+        "source": env!("CARGO_PKG_NAME"),
+        "message": error.to_string(),
+    })
+}
+
+/// Returns an LSP `Position`, which counts characters in UTF-16 code units.
+fn position(offset: usize, text: &str, line_index: &LineIndex<'_>) -> Value {
+    let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+
+    json!({
+        "line": line_index.location(offset).line - 1,
+        "character": text[line_start..offset].encode_utf16().count(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the messages a language server writes for `messages`.
+    fn exchange(messages: &[Value]) -> Vec<Value> {
+        let mut input = Vec::new();
+        for message in messages {
+            write_message(&mut input, message.clone()).unwrap();
+        }
+        let mut output = Vec::new();
+        serve_on(&mut input.as_slice(), &mut output, &Config::default()).unwrap();
+
+        let mut output = output.as_slice();
+        let mut responses = Vec::new();
+        while let Some(message) = read_message(&mut output).unwrap() {
+            responses.push(message);
+        }
+        responses
+    }
+
+    #[test]
+    fn did_open_publishes_diagnostics() {
+        let text = "int main() {\n    printf(\"%s\", (int) x);\n    printf(\"ok\");\n}\n";
+        let responses = exchange(&[
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }),
+            json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didOpen",
+                "params": {
+                    "textDocument": { "uri": "file:///main.c", "languageId": "c", "version": 1, "text": text },
+                },
+            }),
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "shutdown" }),
+            json!({ "jsonrpc": "2.0", "method": "exit" }),
+        ]);
+
+        assert_eq!(responses.len(), 3);
+        assert_eq!(responses[0]["id"], 1);
+        assert_eq!(
+            responses[0]["result"]["capabilities"]["textDocumentSync"],
+            1
+        );
+
+        let params = &responses[1]["params"];
+        assert_eq!(responses[1]["method"], "textDocument/publishDiagnostics");
+        assert_eq!(params["uri"], "file:///main.c");
+        assert_eq!(
+            params["diagnostics"],
+            json!([{
+                "range": {
+                    "start": { "line": 1, "character": 12 },
+                    "end": { "line": 1, "character": 14 },
+                },
+                "severity": 1,
+                "code": "specifier-cast-mismatch",
+                "source": env!("CARGO_PKG_NAME"),
+                "message": "Incorrect specifier for type casted argument.",
+            }])
+        );
+
+        assert_eq!(responses[2]["id"], 2);
+    }
+
+    #[test]
+    fn did_open_valid_document_clears_diagnostics() {
+        let responses = exchange(&[json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": "file:///ok.c", "text": "printf(\"%d\", 1);" } },
+        })]);
+        assert_eq!(responses[0]["params"]["diagnostics"], json!([]));
+    }
+
+    #[test]
+    fn warnings_are_published() {
+        let responses = exchange(&[json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {
+                "textDocument": { "uri": "file:///buf.c", "text": "snprintf(buf, 2, \"x = %d\", x);" },
+            },
+        })]);
+        let diagnostics = &responses[0]["params"]["diagnostics"];
+        assert_eq!(diagnostics[0]["severity"], 2);
+        assert_eq!(diagnostics.as_array().map(Vec::len), Some(1));
+    }
+}
@@ -0,0 +1,134 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+
+/// Object-like macros defined with `#define`, e.g. `#define MAXLEN 64`.
+///
+/// Function-like macros, e.g. `#define MAX(a, b) ...`, are ignored.
+#[derive(Debug, Default)]
+pub struct Macros<'src> {
+    /// Source code the macros were collected from.
+    source: &'src str,
+    defines: HashMap<&'src str, &'src str>,
+    /// Macros that were looked up, so they can be reported as dependencies.
+    used: RefCell<BTreeMap<&'src str, &'src str>>,
+}
+
+impl<'src> Macros<'src> {
+    /// Collects the object-like macros defined in C source code.
+    pub fn collect(source: &'src str) -> Self {
+        let mut macros = HashMap::new();
+
+        for line in source.lines() {
+            let Some(define) = line
+                .trim_start()
+                .strip_prefix('#')
+                .map(str::trim_start)
+                .and_then(|line| line.strip_prefix("define"))
+            else {
+                continue;
+            };
+
+            let define = define.trim_start();
+            let name_len = define
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(define.len());
+            let (name, rest) = define.split_at(name_len);
+
+            // `(` right after the name makes it a function-like macro
+            if name.is_empty() || rest.starts_with('(') {
+                continue;
+            }
+
+            let end = rest.find("//").into_iter().chain(rest.find("/*")).min();
+            macros.insert(name, rest[..end.unwrap_or(rest.len())].trim());
+        }
+
+        Self {
+            source,
+            defines: macros,
+            used: RefCell::default(),
+        }
+    }
+
+    /// Returns the replacement text of a macro, and remembers that it was used.
+    pub fn get(&self, name: &str) -> Option<&'src str> {
+        let (name, value) = self.defines.get_key_value(name.trim())?;
+        self.used.borrow_mut().insert(name, value);
+        Some(value)
+    }
+
+    /// Returns the byte offset of a replacement text in the source code the
+    /// macros were collected from.
+    pub fn offset(&self, value: &'src str) -> usize {
+        value.as_ptr() as usize - self.source.as_ptr() as usize
+    }
+
+    /// Returns the names and replacement texts of the macros that were
+    /// looked up with [`Macros::get`], sorted by name.
+    pub fn into_used(self) -> Vec<(&'src str, &'src str)> {
+        self.used.into_inner().into_iter().collect()
+    }
+
+    /// Returns the value of a macro if it's an integer constant.
+    pub fn int(&self, name: &str) -> Option<u64> {
+        parse_int(self.get(name)?)
+    }
+}
+
+/// Parses a C integer constant, e.g. `64`, `0x40`, `0100`, or `(64u)`.
+pub fn parse_int(source: &str) -> Option<u64> {
+    let mut source = source.trim();
+    while let Some(inner) = source.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+        source = inner.trim();
+    }
+
+    let digits = source.trim_end_matches(['u', 'U', 'l', 'L']);
+    if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        u64::from_str_radix(hex, 16).ok()
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        u64::from_str_radix(&digits[1..], 8).ok()
+    } else {
+        digits.parse().ok()
+    }
+}
+
+/// Returns the number of bytes that text from a string literal stands for,
+/// counting each escape sequence as one byte.
+///
+/// The text can span adjacent string literals, like `a" "b`, and only
+/// what's inside the quotes is counted.
+pub fn unescaped_len(text: &str) -> usize {
+    let mut chars = text.chars().peekable();
+    let mut len = 0;
+    let mut inside = true;
+
+    while let Some(c) = chars.next() {
+        if c == '"' {
+            inside = !inside;
+            continue;
+        }
+        if !inside {
+            continue;
+        }
+
+        if c == '\\' {
+            match chars.next() {
+                Some('0'..='7') => {
+                    for _ in 0..2 {
+                        chars.next_if(|c| matches!(c, '0'..='7'));
+                    }
+                }
+                Some('x') => while chars.next_if(char::is_ascii_hexdigit).is_some() {},
+                _ => {}
+            }
+            len += 1;
+        } else {
+            len += c.len_utf8();
+        }
+    }
+
+    len
+}
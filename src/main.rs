@@ -1,65 +1,664 @@
-mod error;
-mod ir;
-mod lex;
-mod parse;
-use clap::Parser;
-use error::SourceErrors;
-use miette::{Context, IntoDiagnostic};
+mod compile_commands;
+mod lsp;
+use clap::{Parser, ValueEnum};
+use miette::{Context, IntoDiagnostic, Severity};
+use safe_printf::config::{Config, Standard};
+use safe_printf::coverage;
+use safe_printf::error::{self, Diagnostics, Reported};
+use safe_printf::ir::{self, CType};
+use safe_printf::rules::{Level, RuleConfig};
 use std::fmt::Display;
 use std::fs::{self, File};
-use std::io::{BufWriter, Write};
-use std::path::PathBuf;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
 
 /// Validate printf cases in C programs.
+///
+/// Exits with 0 if the source code is valid, 1 if the source code has errors,
+/// and 2 if the command line is invalid or files can't be read or written.
+/// Warnings don't change the exit code unless they're denied with
+/// --deny-warnings, or --error-on warning is passed.
 #[derive(Debug, Parser)]
 #[command(author, version, about)]
 struct Cli {
-    /// File to validate.
-    filepath: PathBuf,
+    /// Files to validate, or `-` to read from stdin.
+    #[arg(required_unless_present_any = ["compile_commands_path", "lsp"])]
+    filepaths: Vec<PathBuf>,
 
-    /// Path to write optimized output to.
-    #[arg(long = "optimize")]
+    /// Path to write optimized output to, `-` for stdout, or a directory to
+    /// write each file's output to if there are several files.
+    #[arg(long = "optimize", conflicts_with = "compile_commands_path")]
     optimize_path: Option<PathBuf>,
 
-    /// Path to write output with type casts format arguments to.
-    #[arg(long = "typecast")]
+    /// Path to write output with type casts format arguments to, `-` for
+    /// stdout, or a directory to write each file's output to if there are
+    /// several files.
+    #[arg(long = "typecast", conflicts_with = "compile_commands_path")]
     typecast_path: Option<PathBuf>,
+
+    /// Path to write output with modernized specifiers to, `-` for stdout, or
+    /// a directory to write each file's output to if there are several files.
+    #[arg(long = "modernize", conflicts_with = "compile_commands_path")]
+    modernize_path: Option<PathBuf>,
+
+    /// Run as a language server over stdin and stdout, publishing diagnostics
+    /// for documents as they're opened and changed.
+    #[arg(long = "lsp", conflicts_with_all = ["filepaths", "compile_commands_path"])]
+    lsp: bool,
+
+    /// Path to a `compile_commands.json`, validating every C file it compiles.
+    #[arg(long = "compile-commands", conflicts_with = "filepaths")]
+    compile_commands_path: Option<PathBuf>,
+
+    /// Typedef that type casts can refer to, e.g. `int32_t=int` or
+    /// `uint32_t=unsigned`. Can be repeated.
+    #[arg(long = "typedef", value_name = "NAME=CTYPE", value_parser = parse_name_ctype)]
+    typedefs: Vec<(String, CType)>,
+
+    /// Return type of a function, e.g. `get_count=int`, so calls to it can be
+    /// type checked. Can be repeated.
+    #[arg(long = "fn-returns", value_name = "NAME=CTYPE", value_parser = parse_name_ctype)]
+    fn_returns: Vec<(String, CType)>,
+
+    /// Report redundant casts, and don't add casts to literals with --typecast.
+    #[arg(long = "warn-redundant-casts")]
+    warn_redundant_casts: bool,
+
+    /// Report `sprintf` and `snprintf` buffers and `asprintf` out-pointers that
+    /// obviously aren't pointers, and sizes that obviously aren't integers.
+    #[arg(long = "check-buffers")]
+    check_buffers: bool,
+
+    /// Accept C++ raw string literals, e.g. `R"(%d)"`, as format strings.
+    #[arg(long = "cpp")]
+    cpp: bool,
+
+    /// Recognize Microsoft's `%C` and `%S` for wide characters and strings.
+    #[arg(long = "ms-compat")]
+    ms_compat: bool,
+
+    /// C standard to validate against, e.g. `c23` to recognize `%b`.
+    #[arg(long = "c-standard", value_enum, default_value_t = CStandard::C17)]
+    c_standard: CStandard,
+
+    /// Report specifiers that are well-defined but likely mistakes, and casts
+    /// that exactly match their specifier.
+    #[arg(long = "pedantic")]
+    pedantic: bool,
+
+    /// How deeply parentheses can be nested in an argument.
+    #[arg(long = "max-nesting", value_name = "DEPTH", default_value_t = 256)]
+    max_nesting: usize,
+
+    /// Report a rule's diagnostics as errors, e.g. `redundant-cast`. Can be repeated.
+    #[arg(long = "deny", value_name = "RULE")]
+    deny: Vec<String>,
+
+    /// Report a rule's diagnostics as warnings. Can be repeated.
+    #[arg(long = "warn", value_name = "RULE")]
+    warn: Vec<String>,
+
+    /// Don't report a rule's diagnostics. Can be repeated.
+    #[arg(long = "allow", value_name = "RULE")]
+    allow: Vec<String>,
+
+    /// Report every warning as an error, e.g. for CI.
+    #[arg(long = "deny-warnings")]
+    deny_warnings: bool,
+
+    /// Don't print the line counting diagnostics by rule after human-readable diagnostics.
+    #[arg(long = "quiet", short = 'q')]
+    quiet: bool,
+
+    /// Format to report diagnostics in.
+    #[arg(long = "format", value_enum, default_value_t = OutputFormat::Human)]
+    format: OutputFormat,
+
+    /// Report a `path:line:col: severity: message [rule]` line for each
+    /// diagnostic, like --format short.
+    #[arg(long = "brief", conflicts_with = "format")]
+    brief: bool,
+
+    /// Render diagnostics in separate groups, each under a header.
+    #[arg(long = "group-by", value_enum)]
+    group_by: Option<GroupBy>,
+
+    /// Order to report diagnostics in.
+    #[arg(long = "sort", value_enum, default_value_t = SortOrder::Source)]
+    sort: SortOrder,
+
+    /// Rewrite each file in place with type casts, like --typecast, if it's valid.
+    #[arg(long = "fix")]
+    fix: bool,
+
+    /// Rewrite each file in place with optimized calls, like --optimize, if it's valid.
+    #[arg(long = "fix-optimize", conflicts_with = "fix")]
+    fix_optimize: bool,
+
+    /// Report how many call sites and arguments --optimize, --typecast, or
+    /// --modernize changed.
+    #[arg(long = "transform-stats", conflicts_with = "compile_commands_path")]
+    transform_stats: bool,
+
+    /// List the `#define`d constants that validation relied on.
+    #[arg(long = "emit-deps")]
+    emit_deps: bool,
+
+    /// Print each call on a single line with whitespace collapsed, e.g. for grepping.
+    #[arg(long = "oneline")]
+    oneline: bool,
+
+    /// List calls that can't be fully validated, e.g. because the format
+    /// string is a variable.
+    #[arg(long = "report-unvalidated")]
+    report_unvalidated: bool,
+
+    /// Print a summary line for each valid file, like `OK: 3 printf sites validated`.
+    #[arg(long = "check")]
+    check: bool,
+
+    /// Only report errors, hiding warnings and advice, like --min-severity error.
+    #[arg(long = "only-errors", conflicts_with = "min_severity")]
+    only_errors: bool,
+
+    /// Hide diagnostics below a severity, e.g. `warning` to hide advice.
+    #[arg(long = "min-severity", value_enum, default_value_t = SeverityLevel::Advice)]
+    min_severity: SeverityLevel,
+
+    /// Exit with 1 if there are diagnostics at or above a severity, e.g.
+    /// `warning` to fail on warnings without reporting them as errors.
+    #[arg(long = "error-on", value_enum, default_value_t = SeverityLevel::Error)]
+    error_on: SeverityLevel,
 }
 
-fn main() -> miette::Result<()> {
-    let cli = Cli::parse();
+/// Formats that diagnostics can be reported in.
+#[derive(Debug, Copy, Clone, ValueEnum)]
+enum OutputFormat {
+    /// Rendered for humans, with source code snippets.
+    Human,
+    /// A JSON array with an object for each file with errors, written to stdout.
+    Json,
+    /// A line for each diagnostic, like `main.c:3:12: error: message [rule]`, written to stdout.
+    Short,
+    /// JUnit XML with a test case for each file, written to stdout.
+    Junit,
+    /// A SARIF 2.1.0 log for code scanning, with a result for each diagnostic, written to stdout.
+    Sarif,
+}
 
-    let source = fs::read_to_string(&cli.filepath)
-        .into_diagnostic()
-        .wrap_err_with(|| format!("failed reading input at {}", cli.filepath.display()))?;
+/// Ways that diagnostics can be grouped when they're rendered.
+#[derive(Debug, Copy, Clone, ValueEnum)]
+enum GroupBy {
+    /// Each file's diagnostics together, under a header with its path.
+    File,
+}
+
+/// Versions of the C standard that can be validated against.
+#[derive(Debug, Copy, Clone, ValueEnum)]
+enum CStandard {
+    /// C17, and the standards before it.
+    C17,
+    /// C23, which adds `%b` for binary integers.
+    C23,
+}
+
+/// Severities that diagnostics can be filtered by.
+#[derive(Debug, Copy, Clone, ValueEnum)]
+enum SeverityLevel {
+    /// Advice, warnings, and errors.
+    Advice,
+    /// Warnings and errors.
+    Warning,
+    /// Only errors.
+    Error,
+}
+
+impl From<SeverityLevel> for Severity {
+    fn from(level: SeverityLevel) -> Self {
+        match level {
+            SeverityLevel::Advice => Severity::Advice,
+            SeverityLevel::Warning => Severity::Warning,
+            SeverityLevel::Error => Severity::Error,
+        }
+    }
+}
+
+/// Orders that diagnostics can be reported in.
+#[derive(Debug, Copy, Clone, ValueEnum)]
+enum SortOrder {
+    /// In the order they appear in the source.
+    Source,
+    /// Errors, then warnings, then advice, each in the order they appear in the source.
+    Severity,
+}
+
+/// Exit code for source code that has errors.
+const EXIT_INVALID: i32 = 1;
+/// Exit code for a command line that's invalid, or files that can't be read
+/// or written, like clap's own exit code for usage errors.
+const EXIT_FAILURE: i32 = 2;
+
+fn main() {
+    if let Err(report) = run() {
+        eprintln!("Error: {report:?}");
+        process::exit(EXIT_FAILURE);
+    }
+}
+
+fn run() -> miette::Result<()> {
+    let mut cli = Cli::parse();
+    if cli.brief {
+        cli.format = OutputFormat::Short;
+    }
+
+    let mut rules = RuleConfig::default();
+    if cli.deny_warnings {
+        rules.deny_warnings();
+    }
+    for (names, level) in [
+        (&cli.allow, Level::Allow),
+        (&cli.warn, Level::Warn),
+        (&cli.deny, Level::Deny),
+    ] {
+        for name in names {
+            rules
+                .set(name, level)
+                .map_err(|msg| miette::miette!("{}", msg))?;
+        }
+    }
+
+    let config = Config {
+        typedefs: cli.typedefs.into_iter().collect(),
+        fn_returns: cli.fn_returns.into_iter().collect(),
+        warn_redundant_casts: cli.warn_redundant_casts,
+        check_buffers: cli.check_buffers,
+        cpp: cli.cpp,
+        ms_compat: cli.ms_compat,
+        standard: match cli.c_standard {
+            CStandard::C17 => Standard::C17,
+            CStandard::C23 => Standard::C23,
+        },
+        pedantic: cli.pedantic,
+        max_nesting: cli.max_nesting,
+        rules,
+        lints: Vec::new(),
+    };
+
+    if cli.lsp {
+        return lsp::serve(&config);
+    }
+
+    let reporting = Reporting {
+        sort: cli.sort,
+        emit_deps: cli.emit_deps,
+        oneline: cli.oneline,
+        report_unvalidated: cli.report_unvalidated,
+        check: cli.check,
+    };
+    let mut diagnostics = Diagnostics::default();
+
+    if let Some(compile_commands_path) = cli.compile_commands_path {
+        for filepath in compile_commands::source_files(&compile_commands_path)? {
+            validate(
+                &filepath,
+                &config,
+                reporting,
+                Outputs {
+                    fix: cli.fix,
+                    fix_optimize: cli.fix_optimize,
+                    ..Outputs::default()
+                },
+                &mut diagnostics,
+            )?;
+        }
+    } else {
+        // with several files, the output paths are directories to write each file's output to
+        let output_dirs = cli.filepaths.len() > 1;
+        for dir in [&cli.optimize_path, &cli.typecast_path, &cli.modernize_path] {
+            if let Some(dir) = dir.as_ref().filter(|dir| output_dirs && !is_stdio(dir)) {
+                fs::create_dir_all(dir)
+                    .into_diagnostic()
+                    .wrap_err_with(|| format!("failed creating directory {}", dir.display()))?;
+            }
+        }
+
+        for filepath in &cli.filepaths {
+            let output_path = |path: &Option<PathBuf>| match path {
+                Some(dir) if output_dirs && !is_stdio(dir) => {
+                    let name = filepath.file_name().filter(|_| !is_stdio(filepath));
+                    Some(dir.join(name.unwrap_or("stdin.c".as_ref())))
+                }
+                path => path.clone(),
+            };
+            let outputs = Outputs {
+                optimize_path: output_path(&cli.optimize_path),
+                typecast_path: output_path(&cli.typecast_path),
+                modernize_path: output_path(&cli.modernize_path),
+                fix: cli.fix,
+                fix_optimize: cli.fix_optimize,
+                stats: cli.transform_stats,
+            };
+
+            validate(filepath, &config, reporting, outputs, &mut diagnostics)?;
+        }
+    }
+
+    // hidden diagnostics still count towards the exit status
+    let failed = diagnostics.has_at_least(cli.error_on.into());
+    let min_severity = match cli.only_errors {
+        true => Severity::Error,
+        false => cli.min_severity.into(),
+    };
+    diagnostics.retain(min_severity);
+
+    // these formats are printed even if every file is valid
+    let report = match cli.format {
+        OutputFormat::Junit => Some(diagnostics.to_junit()),
+        OutputFormat::Sarif => Some(format!("{:#}\n", diagnostics.to_sarif())),
+        OutputFormat::Human | OutputFormat::Json | OutputFormat::Short => None,
+    };
+    if let Some(report) = report {
+        print!("{report}");
+        if failed {
+            process::exit(EXIT_INVALID);
+        }
+        return Ok(());
+    }
+
+    if diagnostics.is_empty() {
+        let produced = cli.check
+            || cli.optimize_path.is_some()
+            || cli.typecast_path.is_some()
+            || cli.modernize_path.is_some()
+            || cli.fix
+            || cli.fix_optimize
+            || cli.emit_deps
+            || cli.oneline
+            || cli.report_unvalidated
+            || !matches!(cli.format, OutputFormat::Human);
+        if failed {
+            process::exit(EXIT_INVALID);
+        }
+        // it's a warning, so hiding warnings hides it too
+        if !produced && matches!(min_severity, Severity::Advice | Severity::Warning) {
+            eprintln!("Warning: no output was produced, pass --check to print a summary, or --optimize, --typecast, or --modernize to write output.");
+        }
+        return Ok(());
+    }
+
+    match cli.format {
+        OutputFormat::Human => {
+            let header = match diagnostics.highest_severity() {
+                Some(Severity::Advice) => "Advice",
+                Some(Severity::Warning) => "Warning",
+                Some(Severity::Error) | None => "Error",
+            };
+            let summary = (!cli.quiet).then(|| diagnostics.summary());
+            match cli.group_by {
+                None => eprintln!("{header}: {:?}", diagnostics.render()),
+                Some(GroupBy::File) => eprint!("{}", group_by_file(diagnostics)),
+            }
+            if let Some(summary) = summary {
+                eprintln!("{summary}");
+            }
+        }
+        OutputFormat::Json => println!("{:#}", diagnostics.to_json()),
+        OutputFormat::Short => print!("{}", diagnostics.to_short()),
+        OutputFormat::Junit | OutputFormat::Sarif => unreachable!("reported above"),
+    }
+    if failed {
+        process::exit(EXIT_INVALID);
+    }
+    Ok(())
+}
 
-    match ir::IntermediateRepresentation::parse(&source) {
+/// Renders each file's diagnostics on their own, under a header with its path.
+fn group_by_file(diagnostics: Diagnostics) -> String {
+    diagnostics
+        .into_files()
+        .into_iter()
+        .map(|file| {
+            let header = format!("==> {} <==", file.filename());
+            format!("{header}\n{:?}\n", miette::Report::new(file))
+        })
+        .collect()
+}
+
+/// What to report about each file besides its diagnostics, and how.
+#[derive(Debug, Copy, Clone)]
+struct Reporting {
+    sort: SortOrder,
+    /// List the macros that validation relied on.
+    emit_deps: bool,
+    /// Print each call on a single line.
+    oneline: bool,
+    /// List the calls that can't be fully validated.
+    report_unvalidated: bool,
+    /// Print a summary line for each valid file.
+    check: bool,
+}
+
+/// Paths to write transformed source code to.
+#[derive(Debug, Default)]
+struct Outputs {
+    optimize_path: Option<PathBuf>,
+    typecast_path: Option<PathBuf>,
+    modernize_path: Option<PathBuf>,
+    /// Rewrite the file in place with type casts.
+    fix: bool,
+    /// Rewrite the file in place with optimized calls.
+    fix_optimize: bool,
+    /// Report what the transformations changed.
+    stats: bool,
+}
+
+/// Validates a single file, writing any requested outputs if it's valid
+/// and adding its errors and warnings to `diagnostics`.
+fn validate(
+    filepath: &Path,
+    config: &Config,
+    reporting: Reporting,
+    outputs: Outputs,
+    diagnostics: &mut Diagnostics,
+) -> miette::Result<()> {
+    let source = read_source(filepath)?;
+    if (outputs.fix || outputs.fix_optimize) && is_stdio(filepath) {
+        return Err(miette::miette!(
+            "stdin can't be rewritten in place, write to stdout with `--typecast -` or `--optimize -` instead"
+        ));
+    }
+    let filepath = match is_stdio(filepath) {
+        true => Path::new("<stdin>"),
+        false => filepath,
+    };
+
+    if reporting.report_unvalidated {
+        for call in coverage::unvalidated(&source, config) {
+            println!(
+                "{}:{}:{}: `{}` call not validated, {}",
+                filepath.display(),
+                call.location.line,
+                call.location.column,
+                call.function.name(),
+                call.reason
+            );
+        }
+    }
+
+    match ir::IntermediateRepresentation::parse(&source, config) {
         Ok(repr) => {
-            if let Some(optimize_path) = cli.optimize_path {
+            if reporting.check {
+                let sites = repr.sites().count();
+                println!(
+                    "OK: {sites} printf {} validated in {}",
+                    if sites == 1 { "site" } else { "sites" },
+                    filepath.display()
+                );
+            }
+
+            if reporting.emit_deps {
+                for (name, value) in repr.deps() {
+                    println!("{}: #define {name} {value}", filepath.display());
+                }
+            }
+
+            if reporting.oneline {
+                for site in repr.sites() {
+                    println!(
+                        "{}:{}: {}",
+                        filepath.display(),
+                        site.location().line,
+                        site.oneline()
+                    );
+                }
+            }
+
+            let transformed = outputs.optimize_path.is_some()
+                || outputs.typecast_path.is_some()
+                || outputs.modernize_path.is_some()
+                || outputs.fix
+                || outputs.fix_optimize;
+
+            // optimized calls can't take `*` widths and precisions, so that
+            // output isn't written unless the rule is downgraded
+            let unoptimizable = match outputs.optimize_path.is_some() {
+                true => config.rules.apply(repr.unoptimizable()),
+                false => Vec::new(),
+            };
+            let optimizable = !unoptimizable.iter().any(Reported::is_error);
+
+            if let Some(optimize_path) = outputs.optimize_path.filter(|_| optimizable) {
                 write(repr.display_optimize(), "optimize", optimize_path)?;
             }
 
-            if let Some(typecast_path) = cli.typecast_path {
+            if let Some(typecast_path) = outputs.typecast_path {
                 write(repr.display_typecast(), "typecast", typecast_path)?;
             }
 
-            Ok(())
+            if let Some(modernize_path) = outputs.modernize_path {
+                write(repr.display_modernize(), "modernize", modernize_path)?;
+            }
+
+            if outputs.fix {
+                write_in_place(repr.display_typecast(), "fix", filepath)?;
+            }
+
+            if outputs.fix_optimize {
+                write_in_place(repr.display_optimize(), "fix-optimize", filepath)?;
+            }
+
+            if outputs.stats && transformed {
+                eprintln!("{}: {}", filepath.display(), repr.stats());
+            }
+
+            let mut warnings = repr.into_warnings();
+            warnings.extend(unoptimizable);
+            warnings.sort_by_key(Reported::offset);
+            report_errors(filepath, source, warnings, &reporting, diagnostics)
         }
-        Err(errors) => Err(SourceErrors::new(cli.filepath, source, errors).into()),
+        Err(errors) => report_errors(filepath, source, errors, &reporting, diagnostics),
+    }
+}
+
+/// Adds the errors, warnings, and advice from validating a file to
+/// `diagnostics`, sorted as requested, or records it as passed if there are
+/// none.
+fn report_errors(
+    filepath: &Path,
+    source: String,
+    mut errors: Vec<Reported>,
+    reporting: &Reporting,
+    diagnostics: &mut Diagnostics,
+) -> miette::Result<()> {
+    if let SortOrder::Severity = reporting.sort {
+        error::sort_by_severity(&mut errors);
+    }
+
+    diagnostics.add(filepath.to_path_buf(), source, errors);
+    Ok(())
+}
+
+/// Replaces a file with the output of a transformation, by writing it to a
+/// temporary file next to it first and renaming that over the original, so
+/// the file is never left half written.
+///
+/// The replacement keeps the permissions of the original, and the temporary
+/// file is removed if anything fails after it's created.
+fn write_in_place(repr: impl Display, kind: &str, path: &Path) -> miette::Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path = path.with_file_name(format!(".{name}.{}.tmp", env!("CARGO_PKG_NAME")));
+
+    let permissions = fs::metadata(path)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed reading metadata of {}", path.display()))?
+        .permissions();
+    let file = create(kind, &temp_path)?;
+
+    let result = write_to(repr, kind, file)
+        .and_then(|()| {
+            fs::set_permissions(&temp_path, permissions)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Failed copying permissions of {}", path.display()))
+        })
+        .and_then(|()| {
+            fs::rename(&temp_path, path)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Failed replacing {} for --{kind}", path.display()))
+        });
+
+    if result.is_err() {
+        // best effort, the error that got here is the one worth reporting
+        let _ = fs::remove_file(&temp_path);
     }
+    result
 }
 
+/// Returns `true` if `path` is `-`, which stands for stdin or stdout.
+fn is_stdio(path: &Path) -> bool {
+    path.to_str() == Some("-")
+}
+
+/// Reads a file, or all of stdin if `filepath` is `-`, which may be empty.
+fn read_source(filepath: &Path) -> miette::Result<String> {
+    if is_stdio(filepath) {
+        let mut source = String::new();
+        return io::stdin()
+            .read_to_string(&mut source)
+            .map(|_| source)
+            .into_diagnostic()
+            .wrap_err("failed reading input from stdin");
+    }
+
+    fs::read_to_string(filepath)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("failed reading input at {}", filepath.display()))
+}
+
+/// Writes the output of a transformation to a new file, or to stdout if `path` is `-`.
 fn write(repr: impl Display, kind: &str, path: PathBuf) -> miette::Result<()> {
-    let file = File::options()
+    if is_stdio(&path) {
+        write_to(repr, kind, io::stdout().lock())
+    } else {
+        write_to(repr, kind, create(kind, &path)?)
+    }
+}
+
+/// Creates a new file for the output of a transformation, failing if it already exists.
+fn create(kind: &str, path: &Path) -> miette::Result<File> {
+    File::options()
         .create_new(true)
         .write(true)
-        .open(&path)
+        .open(path)
         .into_diagnostic()
-        .wrap_err_with(|| format!("Failed creating output for --{kind}: {}", path.display()))?;
+        .wrap_err_with(|| format!("Failed creating output for --{kind}: {}", path.display()))
+}
 
-    let mut writer = BufWriter::new(file);
+/// Writes the output of a transformation to a writer, buffering it.
+fn write_to(repr: impl Display, kind: &str, writer: impl Write) -> miette::Result<()> {
+    let mut writer = BufWriter::new(writer);
 
-    writeln!(&mut writer, "{}", repr)
+    write!(&mut writer, "{}", repr)
         .into_diagnostic()
         .wrap_err_with(|| format!("Failed writing to file for --{kind}"))?;
 
@@ -70,3 +669,143 @@ fn write(repr: impl Display, kind: &str, path: PathBuf) -> miette::Result<()> {
 
     Ok(())
 }
+
+fn parse_name_ctype(s: &str) -> Result<(String, CType), String> {
+    let (name, ctype) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected `NAME=CTYPE`, found `{s}`"))?;
+
+    Ok((name.trim().to_string(), ctype.trim().parse()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a new empty directory for a test to write files in.
+    pub(crate) fn scratch_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("safe_printf-{test}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn typedefs_name_a_ctype() {
+        let cli =
+            Cli::try_parse_from(["safe_printf", "--typedef", "int32_t=int", "main.c"]).unwrap();
+        assert_eq!(cli.typedefs, [("int32_t".to_string(), CType::Int)]);
+        let cli = Cli::try_parse_from(["safe_printf", "--typedef", "uint32_t=unsigned", "main.c"])
+            .unwrap();
+        assert_eq!(cli.typedefs, [("uint32_t".to_string(), CType::Unsigned)]);
+        assert!(Cli::try_parse_from(["safe_printf", "--typedef", "int32_t", "main.c"]).is_err());
+    }
+
+    #[test]
+    fn group_by_file_renders_each_file() {
+        let mut diagnostics = Diagnostics::default();
+        for (name, source) in [
+            ("main.c", "printf(\"%d\");"),
+            ("ok.c", "printf(\"%d\", x);"),
+            ("util.c", "printf(\"%s\", 5);"),
+        ] {
+            let errors = ir::IntermediateRepresentation::parse(source, &Config::default())
+                .err()
+                .unwrap_or_default();
+            diagnostics.add(name.into(), source.to_string(), errors);
+        }
+
+        let rendered = group_by_file(diagnostics);
+        let main = rendered.find("==> main.c <==").unwrap();
+        let util = rendered.find("==> util.c <==").unwrap();
+        assert!(main < util);
+        assert!(!rendered.contains("ok.c"));
+        assert!(rendered[main..util].contains("Excess specifiers"));
+        assert!(!rendered[main..util].contains("Incorrect specifier"));
+        assert!(rendered[util..].contains("Incorrect specifier for argument."));
+    }
+
+    #[test]
+    fn write_in_place_replaces_file() {
+        let dir = scratch_dir("replaces");
+        let path = dir.join("main.c");
+        fs::write(&path, "before").unwrap();
+
+        write_in_place("after", "fix", &path).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "after");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_in_place_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = scratch_dir("permissions");
+        let path = dir.join("script.c");
+        fs::write(&path, "before").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o750)).unwrap();
+
+        write_in_place("after", "fix", &path).unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o750);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn write_in_place_removes_temp_file_on_error() {
+        let dir = scratch_dir("cleanup");
+        // a file can't be renamed over a directory
+        let path = dir.join("main.c");
+        fs::create_dir(&path).unwrap();
+
+        assert!(write_in_place("after", "fix", &path).is_err());
+
+        let names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["main.c"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn validate_writes_outputs_with_warnings() {
+        let dir = scratch_dir("warnings");
+        let path = dir.join("main.c");
+        fs::write(&path, "printf(\"%d\\n\", x, y);\n").unwrap();
+        let typecast_path = dir.join("typecast.c");
+
+        let reporting = Reporting {
+            sort: SortOrder::Source,
+            emit_deps: false,
+            oneline: false,
+            report_unvalidated: false,
+            check: false,
+        };
+        let outputs = Outputs {
+            typecast_path: Some(typecast_path.clone()),
+            ..Outputs::default()
+        };
+        let mut diagnostics = Diagnostics::default();
+        validate(
+            &path,
+            &Config::default(),
+            reporting,
+            outputs,
+            &mut diagnostics,
+        )
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&typecast_path).unwrap(),
+            "printf(\"%d\\n\", (int) (x), y);\n"
+        );
+        assert!(!diagnostics.is_empty());
+        assert!(!diagnostics.has_errors());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,7 +1,10 @@
+use crate::config::{Config, Standard};
 use crate::error::Error;
 use crate::ir::CType;
 use crate::lex::{ArgToken, FormatToken, SourceToken};
+use crate::macros::Macros;
 use logos::{Lexer, Logos};
+use std::borrow::Cow;
 use std::ops::Range;
 
 /// An argument in a function call.
@@ -11,40 +14,236 @@ use std::ops::Range;
 pub struct Arg<'src> {
     /// The token, if there's exactly one (skipping comments and whitespaces)
     pub single_token: Option<ArgToken<'src>>,
+    /// Source code of the single token, e.g. `5L` in `(5L)`
+    pub single_source: Option<&'src str>,
+    /// The first token, even if there are more (skipping comments and whitespaces)
+    pub first_token: Option<ArgToken<'src>>,
     /// Range in source code
     pub span: Range<usize>,
     /// Type cast of the argument, if present
     pub cast: Option<(CType, Range<usize>)>,
 }
 
+impl Arg<'_> {
+    /// Returns the C type of the argument if it's obvious from the syntax,
+    /// e.g. `5`, `(5UL)`, `(1.0L)`, `"hello"`, `argv[1]`, `&x`, `sizeof(x)`,
+    /// or a call to a function in [`Config::fn_returns`]. `source` is the
+    /// argument's source code.
+    pub fn inferred_ctype(&self, source: &str, config: &Config) -> Option<CType> {
+        // literal suffixes are read from the literal itself, not its parentheses
+        let literal = self.single_source.unwrap_or(source);
+        match self.single_token {
+            Some(ArgToken::Int) => Some(int_literal_ctype(literal)),
+            Some(ArgToken::Char) => Some(CType::Char),
+            Some(ArgToken::Float) if literal.ends_with(['l', 'L']) => Some(CType::LongDouble),
+            Some(ArgToken::Float) => Some(CType::Float),
+            Some(ArgToken::String(_)) => Some(CType::String),
+            Some(ArgToken::Identifier("argv")) => Some(CType::Pointer),
+            _ if is_argv_index(source) => Some(CType::String),
+            _ if is_address_of(source) => Some(CType::Pointer),
+            _ if is_sizeof(source) => Some(CType::Size),
+            _ => self.return_ctype(source, config),
+        }
+    }
+
+    /// Returns the C type the argument evaluates to if it's a single call to
+    /// a function in [`Config::fn_returns`], e.g. `get_count()`.
+    pub fn return_ctype(&self, source: &str, config: &Config) -> Option<CType> {
+        let Some(ArgToken::Identifier(name)) = self.first_token else {
+            return None;
+        };
+        let args = source.strip_prefix(name)?.trim_start().strip_prefix('(')?;
+
+        // make sure the call's parentheses close at the very end, which
+        // rules out `get_count() + 1` and `get_fn()(2)`
+        let mut opened = 0u32;
+        for (i, c) in args.char_indices() {
+            match c {
+                '(' => opened += 1,
+                ')' => match opened.checked_sub(1) {
+                    Some(n) => opened = n,
+                    None if i + 1 == args.len() => return config.fn_returns.get(name).copied(),
+                    None => return None,
+                },
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+/// Returns the C type of an integer literal from its suffix, e.g. `long`
+/// for `5L` or `unsigned long` for `5UL`.
+fn int_literal_ctype(source: &str) -> CType {
+    let digits = source.trim_end_matches(['u', 'U', 'l', 'L', 'z', 'Z']);
+    let suffix = source[digits.len()..].to_ascii_lowercase();
+    let unsigned = suffix.contains('u');
+    match suffix.trim_matches('u') {
+        "l" if unsigned => CType::UnsignedLong,
+        "l" => CType::Long,
+        "ll" if unsigned => CType::UnsignedLongLong,
+        "ll" => CType::LongLong,
+        "z" => CType::Size,
+        _ if unsigned => CType::Unsigned,
+        _ => CType::Int,
+    }
+}
+
+/// Returns `true` if `source` is a single `sizeof`, like `sizeof(int)` or
+/// `sizeof x`, which is a `size_t`.
+fn is_sizeof(source: &str) -> bool {
+    let Some(operand) = source.strip_prefix("sizeof") else {
+        return false;
+    };
+    let Some(inner) = operand.trim_start().strip_prefix('(') else {
+        // without parentheses, the operand has to be a single identifier
+        let operand = operand.trim_start();
+        return operand.len() < source.len() - "sizeof".len()
+            && !operand.is_empty()
+            && operand
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'_');
+    };
+
+    // make sure the parentheses close at the very end, which rules out
+    // `sizeof(x) - 1`
+    let mut opened = 0u32;
+    for (i, c) in inner.char_indices() {
+        match c {
+            '(' => opened += 1,
+            ')' => match opened.checked_sub(1) {
+                Some(n) => opened = n,
+                None => return i + 1 == inner.len(),
+            },
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Returns `true` if `source` takes the address of something, like `&x`.
+pub fn is_address_of(source: &str) -> bool {
+    source.starts_with('&') && !source.starts_with("&&")
+}
+
+/// Returns `true` if `source` indexes once into `argv`, like `argv[i + 1]`.
+fn is_argv_index(source: &str) -> bool {
+    let Some(index) = source
+        .strip_prefix("argv")
+        .map(str::trim_start)
+        .and_then(|rest| rest.strip_prefix('['))
+        .and_then(|rest| rest.strip_suffix(']'))
+    else {
+        return false;
+    };
+
+    // make sure the brackets match, which rules out `argv[0][1]`
+    let mut opened = 0u32;
+    for c in index.chars() {
+        match c {
+            '[' => opened += 1,
+            ']' => match opened.checked_sub(1) {
+                Some(n) => opened = n,
+                None => return false,
+            },
+            _ => {}
+        }
+    }
+    opened == 0
+}
+
+/// Returns `true` if `source` picks between two string literals with `?:`,
+/// like `cond ? "%d" : (const char*) "%s"`, seeing through casts to `char*`.
+fn is_literal_ternary(mut source: &str) -> bool {
+    while let Some(inner) = source
+        .trim()
+        .strip_prefix('(')
+        .and_then(|s| s.strip_suffix(')'))
+    {
+        source = inner;
+    }
+
+    // split on `?` and `:` tokens, so ones inside string literals don't count
+    let mut lex = ArgToken::lexer(source);
+    let mut opened = 0u32;
+    let mut question = None;
+    let mut colon = None;
+    while let Some(token) = lex.next() {
+        match (token, lex.slice()) {
+            (ArgToken::LParen | ArgToken::LBracket, _) => opened += 1,
+            (ArgToken::RParen | ArgToken::RBracket, _) => opened = opened.saturating_sub(1),
+            (ArgToken::Symbol, "?") if opened == 0 && question.is_none() => {
+                question = Some(lex.span());
+            }
+            (ArgToken::Symbol, ":") if opened == 0 && question.is_some() => {
+                colon = Some(lex.span());
+                break;
+            }
+            _ => {}
+        }
+    }
+    let (Some(question), Some(colon)) = (question, colon) else {
+        return false;
+    };
+    is_string_literal(&source[question.end..colon.start]) && is_string_literal(&source[colon.end..])
+}
+
+/// Returns `true` if `source` is a string literal, possibly cast to `char*`.
+fn is_string_literal(source: &str) -> bool {
+    let mut source = source.trim();
+    if let Some((cast, rest)) = source.strip_prefix('(').and_then(|s| s.split_once(')')) {
+        let cast: String = cast.split_whitespace().collect();
+        if cast == "char*" || cast == "constchar*" {
+            source = rest;
+        }
+    }
+
+    let mut lex = ArgToken::lexer(source);
+    matches!(lex.next(), Some(ArgToken::String(_))) && lex.next().is_none()
+}
+
 /// [`Iterator`] over [`Arg`]s in `printf` call e.g. `"input"` and `"4"` in `"printf("%s %d", input, 4)"`.
 #[derive(Debug)]
 pub struct Args<'lex, 'src> {
     // hold onto source_lex so we can bump it when done parsing
     source_lex: &'lex mut Lexer<'src, SourceToken<'src>>,
     lex: Lexer<'src, ArgToken<'src>>,
+    config: &'lex Config,
     has_remaining: Option<()>,
     start: usize,
     end: usize,
+    /// The first `(` nested deeper than [`Config::max_nesting`], if any.
+    excessive_nesting: Option<Range<usize>>,
+    /// The `(` opening the call, if the source code ends before it's closed.
+    unterminated: Option<Range<usize>>,
+    /// The `,` that the last argument ended at, if any.
+    last_comma: Option<Range<usize>>,
+    /// The first `,` followed by an empty argument, e.g. in `x,)` or `x,,y`.
+    empty_argument: Option<Range<usize>>,
 }
 
 impl<'lex, 'src> Args<'lex, 'src> {
     /// Returns a new [`Args`].
-    pub fn new(source_lex: &'lex mut Lexer<'src, SourceToken<'src>>) -> Self {
+    pub fn new(source_lex: &'lex mut Lexer<'src, SourceToken<'src>>, config: &'lex Config) -> Self {
         let mut lex = ArgToken::lexer(source_lex.source());
         let start = source_lex.span().end;
         lex.bump(start);
         Args {
             source_lex,
             lex,
+            config,
             has_remaining: Some(()),
             start,
             end: start,
+            excessive_nesting: None,
+            unterminated: None,
+            last_comma: None,
+            empty_argument: None,
         }
     }
 
     /// Returns the number of remaining arguments, as well as their combined spans.
-    pub fn short_circuit(mut self) -> (usize, Range<usize>) {
+    pub fn short_circuit(&mut self) -> (usize, Range<usize>) {
         let remaining = self.by_ref().count();
         (remaining, self.start..self.end)
     }
@@ -53,18 +252,125 @@ impl<'lex, 'src> Args<'lex, 'src> {
         &self.source_lex.source()[span]
     }
 
+    /// Returns the byte offset of `text` in the source code, which it must be a slice of.
+    pub fn offset(&self, text: &'src str) -> usize {
+        text.as_ptr() as usize - self.source_lex.source().as_ptr() as usize
+    }
+
     /// Parses the next argument as a format string, or returns an error.
-    pub fn next_format_string(&mut self) -> Result<(&'src str, Range<usize>), Error> {
+    ///
+    /// Returns the contents of the string literal, its span including the
+    /// quotes, and the name of the macro it was defined with if it was passed
+    /// by name, like `FMT` after `#define FMT "%d\n"`.
+    pub fn next_format_string(
+        &mut self,
+        macros: &Macros<'src>,
+    ) -> Result<(&'src str, Range<usize>, Option<&'src str>), Error> {
         match self.next() {
             Some(Arg {
                 single_token: Some(ArgToken::String(format)),
                 span,
                 ..
-            }) => Ok((format, span)),
-            Some(arg) => Err(Error::nonliteral(arg)),
+            }) => Ok((format, span, None)),
+            Some(Arg {
+                single_token: Some(ArgToken::RawString(format)),
+                span,
+                ..
+            }) if self.config.cpp => Ok((format, span, None)),
+            Some(arg) => {
+                if let Some(ArgToken::Identifier(name)) = arg.single_token {
+                    if let Some((format, span)) = self.macro_format(name, macros) {
+                        return Ok((format, span, Some(name)));
+                    }
+                }
+
+                let literal_ternary = is_literal_ternary(self.source(arg.span.clone()));
+                Err(Error::nonliteral(arg, literal_ternary))
+            }
             None => Err(Error::MissingFunctionArgs(self.start..self.end)),
         }
     }
+
+    /// Returns the contents and span of the string literal that an
+    /// object-like macro is defined as, if it's exactly one string literal.
+    fn macro_format(&self, name: &str, macros: &Macros<'src>) -> Option<(&'src str, Range<usize>)> {
+        let value = macros.get(name)?;
+        let mut lex = ArgToken::lexer(value);
+        let format = match lex.next()? {
+            ArgToken::String(format) => format,
+            ArgToken::RawString(format) if self.config.cpp => format,
+            _ => return None,
+        };
+        let span = lex.span();
+        if lex.next().is_some() {
+            return None;
+        }
+
+        let start = macros.offset(value) + span.start;
+        Some((format, start..start + span.len()))
+    }
+
+    /// Returns the span of the first `(` nested deeper than
+    /// [`Config::max_nesting`], if any.
+    pub fn excessive_nesting(&self) -> Option<Range<usize>> {
+        self.excessive_nesting.clone()
+    }
+
+    /// Returns the span of the `(` opening the call, if the source code ends
+    /// before it's closed.
+    pub fn unterminated(&self) -> Option<Range<usize>> {
+        self.unterminated.clone()
+    }
+
+    /// Returns the span of the first `,` followed by an empty argument, if
+    /// any, like in `printf("%d", x,)`. Empty arguments are skipped.
+    pub fn empty_argument(&self) -> Option<Range<usize>> {
+        self.empty_argument.clone()
+    }
+
+    /// Records an empty argument after the last `,`, or before `comma` if
+    /// it's the first argument.
+    fn empty(&mut self, comma: Range<usize>) {
+        let comma = self.last_comma.clone().unwrap_or(comma);
+        self.empty_argument.get_or_insert(comma);
+    }
+
+    /// Counts the `(` that was just lexed as one more level of nesting.
+    fn open(&mut self, opened: &mut usize) {
+        *opened += 1;
+        if *opened > self.config.max_nesting && self.excessive_nesting.is_none() {
+            self.excessive_nesting = Some(self.lex.span());
+        }
+    }
+
+    /// Returns `true` if the token that was just lexed starts a postfix
+    /// operator, like `[` in `a[0]` or `.` in `p.x`.
+    fn is_postfix(&self, token: ArgToken<'src>) -> bool {
+        match token {
+            ArgToken::LBracket => matches!(self.lex.slice(), "[" | "<:"),
+            ArgToken::Symbol => matches!(self.lex.slice(), "." | "->"),
+            _ => false,
+        }
+    }
+
+    /// Checks if the `(` that was just lexed opens a cast to a registered
+    /// typedef, e.g. `(uint32_t)`, and consumes the cast if it does.
+    fn typedef_cast(&mut self) -> Option<(CType, Range<usize>)> {
+        let start = self.lex.span().start;
+        let mut lookahead = self.lex.clone();
+
+        let ArgToken::Identifier(name) = lookahead.next()? else {
+            return None;
+        };
+        let ctype = *self.config.typedefs.get(name)?;
+        if lookahead.next()? != ArgToken::RParen {
+            return None;
+        }
+
+        let span = start..lookahead.span().end;
+        self.lex = lookahead;
+        Some((ctype, span))
+    }
 }
 
 impl<'lex, 'src> Iterator for Args<'lex, 'src> {
@@ -75,21 +381,71 @@ impl<'lex, 'src> Iterator for Args<'lex, 'src> {
 
         let mut cast = None;
         let mut span = None;
-        let mut opened = 0u32;
+        let mut opened = 0usize;
+        // brackets and braces, e.g. in `a[i]` or `(int[]){1, 2}`
+        let mut bracketed = 0usize;
+        // the cast starts a compound literal, like `(int){5}`
+        let mut compound = false;
         let mut single_token = None;
+        let mut single_source = None;
+        let mut first_token = None;
         let mut count = 0u32;
+        // the parentheses after `sizeof` hold a type, not a cast, like in `sizeof(int)`
+        let mut after_sizeof = false;
 
         loop {
-            match self.lex.next()? {
-                ArgToken::Comma if opened == 0 => {
+            let Some(token) = self.lex.next() else {
+                // the source code ended before the call was closed
+                self.has_remaining = None;
+                self.unterminated = Some(self.start - 1..self.start);
+                self.source_lex.bump(self.source_lex.remainder().len());
+                return None;
+            };
+            if token == ArgToken::LParen && count > 0 {
+                // a call like `get_format()` isn't just its name
+                single_token = None;
+                single_source = None;
+            }
+
+            if compound && opened == 0 && bracketed == 0 && self.is_postfix(token) {
+                // like `(char*){"a"}[0]`, which isn't a `char*` anymore
+                cast = None;
+                compound = false;
+            }
+
+            match token {
+                ArgToken::LBracket => bracketed += 1,
+                ArgToken::RBracket => bracketed = bracketed.saturating_sub(1),
+                _ => {}
+            }
+
+            match token {
+                ArgToken::Comma if opened == 0 && bracketed == 0 => {
+                    let Some(span) = span else {
+                        // nothing between this comma and the last, skip it
+                        self.empty(self.lex.span());
+                        self.last_comma = Some(self.lex.span());
+                        continue;
+                    };
+
                     // parsed an argument, now expecting another
+                    self.last_comma = Some(self.lex.span());
                     return Some(Arg {
                         single_token,
-                        span: span?,
+                        single_source,
+                        first_token,
+                        span,
                         cast,
                     });
                 }
-                ArgToken::LParen => opened = opened.checked_add(1).expect("overflow"),
+                ArgToken::LParen if cast.is_none() && !after_sizeof => match self.typedef_cast() {
+                    Some((ctype, cast_span)) => {
+                        span = Some(union(span, cast_span.clone()));
+                        cast = Some((ctype, cast_span));
+                    }
+                    None => self.open(&mut opened),
+                },
+                ArgToken::LParen => self.open(&mut opened),
                 ArgToken::RParen => match opened.checked_sub(1) {
                     Some(n) => opened = n,
                     None => {
@@ -97,23 +453,37 @@ impl<'lex, 'src> Iterator for Args<'lex, 'src> {
                         self.has_remaining = None;
                         self.end = self.lex.span().start;
                         self.source_lex.bump(self.end - self.start + 1);
+                        if span.is_none() && self.last_comma.is_some() {
+                            // a trailing comma, like in `x,)`
+                            self.empty(self.lex.span());
+                        }
                         return Some(Arg {
                             single_token,
+                            single_source,
+                            first_token,
                             span: span?,
                             cast,
                         });
                     }
                 },
-                ArgToken::TypeCast(ctype) if cast.is_none() => {
-                    cast = Some((ctype, self.lex.span()))
+                ArgToken::TypeCast(ctype) if cast.is_none() && !after_sizeof => {
+                    cast = Some((ctype, self.lex.span()));
+                    let mut lookahead = self.lex.clone();
+                    compound = lookahead.next() == Some(ArgToken::LBracket)
+                        && matches!(lookahead.slice(), "{" | "<%");
                 }
                 token => {
                     single_token = (count == 0).then_some(token);
+                    single_source = (count == 0).then(|| self.lex.slice());
+                    if count == 0 {
+                        first_token = Some(token);
+                    }
                     count += 1;
                 }
             }
 
             span = Some(union(span, self.lex.span()));
+            after_sizeof = token == ArgToken::Identifier("sizeof");
 
             self.end = self.lex.span().end;
         }
@@ -125,19 +495,149 @@ impl<'lex, 'src> Iterator for Args<'lex, 'src> {
 /// This type is returned by [`Specifiers`] on iteration.
 #[derive(Debug)]
 pub struct Specifier<'src> {
+    /// The `2` part of `printf("%2$s %1$d", 5, "five")`, the position of the
+    /// argument to format, if it's given.
+    pub position: Option<usize>,
     /// The `-2.3` part of `printf("%-2.3f", 3.141)`.
     pub options: &'src str,
+    /// The `lf` part of `printf("%-2.3lf", 3.141)`, as written.
+    pub conversion: &'src str,
     /// The C type corresponding to the specifier e.g. `float` for `%f`.
     pub ctype: CType,
 }
 
 impl<'src> Specifier<'src> {
-    /// Returns a new [`Specifier`].
-    pub fn new(options: &'src str, ctype: CType) -> Self {
-        Self { options, ctype }
+    /// Returns a new [`Specifier`] from its source code, e.g. `%-2.3f`.
+    pub fn new(source: &'src str, ctype: CType) -> Self {
+        let options_end = source
+            .find(|c: char| c.is_ascii_alphabetic())
+            .unwrap_or(source.len());
+        // a position is digits then `$` right after the `%`, unlike the one
+        // in a width from an argument by position like `*2$`
+        let (position, options_start) = match source[1..options_end].split_once('$') {
+            Some((position, _)) if position.bytes().all(|b| b.is_ascii_digit()) => {
+                (position.parse().ok(), position.len() + 2)
+            }
+            _ => (None, 1),
+        };
+
+        Self {
+            position,
+            options: &source[options_start..options_end],
+            conversion: &source[options_end..],
+            ctype,
+        }
+    }
+
+    /// Returns `true` if a value of type `ctype` can be passed for the
+    /// specifier, e.g. `char` for `%d`.
+    pub fn accepts(&self, ctype: CType) -> bool {
+        ctype.formats_as(self.ctype)
+            // hexadecimal, octal, and binary take an unsigned value, but a signed one is common too
+            || (matches!(self.conversion_char(), 'x' | 'X' | 'o' | 'b' | 'B')
+                && ctype.formats_as(self.ctype.unsigned()))
+            || (self.is_promoted()
+                && (ctype.formats_as(CType::Int) || ctype.formats_as(CType::Unsigned)))
+    }
+
+    /// Returns `true` if the specifier has an `h` or `hh` length modifier,
+    /// whose value is promoted to an `int` when it's passed, e.g. `%hhd`.
+    pub fn is_promoted(&self) -> bool {
+        matches!(self.length_modifier(), "h" | "hh")
+    }
+
+    /// Returns the conversion without its length modifier, e.g. `x` for `%llx`.
+    pub fn conversion_char(&self) -> char {
+        self.conversion
+            .chars()
+            .last()
+            .expect("specifiers end with a conversion")
+    }
+
+    /// Returns the conversion with deprecated ones replaced by their modern
+    /// equivalent, e.g. `d` for `i`, but keeping ones that format the type
+    /// differently than usual, e.g. `x` for hexadecimal or `e` for
+    /// scientific notation.
+    pub fn modern_conversion(&self) -> Cow<'src, str> {
+        match (self.ctype, self.conversion) {
+            (_, "D") | (CType::WideChar | CType::WideString, _) => {
+                Cow::Borrowed(self.ctype.specifier())
+            }
+            // the `l` in `%lf` or `%le` has no effect
+            (CType::Float, conversion) => Cow::Borrowed(conversion.trim_start_matches('l')),
+            // `d` is the usual spelling, but keep the length modifier, e.g. `%lld` for `%lli`
+            (_, conversion) => match conversion.strip_suffix('i') {
+                Some(modifier) => Cow::Owned(format!("{modifier}d")),
+                None => Cow::Borrowed(conversion),
+            },
+        }
+    }
+
+    /// Returns how many `int` arguments the width and precision are passed
+    /// in, e.g. 2 for `%*.*f`.
+    pub fn star_args(&self) -> usize {
+        self.options.matches('*').count()
+    }
+
+    /// Returns the offset of each `*` width and precision in the options,
+    /// with the position of its argument if it's given, e.g. `(1, Some(2))`
+    /// for the `*2$` in `%1$-*2$d`.
+    pub fn star_positions(&self) -> impl Iterator<Item = (usize, Option<usize>)> + '_ {
+        self.options.match_indices('*').map(|(offset, _)| {
+            let after = &self.options[offset + 1..];
+            let digits = after
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(after.len());
+            let position = after[digits..]
+                .starts_with('$')
+                .then(|| after[..digits].parse().ok())
+                .flatten();
+            (offset, position)
+        })
+    }
+
+    /// Returns the length modifier of the specifier, e.g. `ll` for `%lld`.
+    pub fn length_modifier(&self) -> &'src str {
+        &self.conversion[..self.conversion.len() - 1]
+    }
+
+    /// Name of our function ptr that optimizes a print for the specifier,
+    /// which is the one for its C type unless it's formatted differently,
+    /// e.g. in hexadecimal.
+    pub fn format_fn(&self) -> &'static str {
+        match self.conversion_char() {
+            'x' => "fmt_hex",
+            'X' => "fmt_hex_upper",
+            'o' => "fmt_octal",
+            'b' => "fmt_binary",
+            'B' => "fmt_binary_upper",
+            // e.g. `%hhd` formats a number, not a `char`
+            'u' if self.is_promoted() => CType::Unsigned.format_fn(),
+            _ if self.is_promoted() => CType::Int.format_fn(),
+            _ => self.ctype.format_fn(),
+        }
+    }
+
+    /// Returns the options with flags in a canonical order and without
+    /// duplicates, e.g. `-+5` for `+-+5`.
+    pub fn canonical_options(&self) -> String {
+        let flags_len = self
+            .options
+            .find(|c| !FLAGS.contains(c))
+            .unwrap_or(self.options.len());
+        let (flags, rest) = self.options.split_at(flags_len);
+
+        FLAGS
+            .chars()
+            .filter(|flag| flags.contains(*flag))
+            .chain(rest.chars())
+            .collect()
     }
 }
 
+/// Flags that can start the options of a specifier, in canonical order.
+const FLAGS: &str = "-+ #0";
+
 /// [`Iterator`] over [`Specifier`]s in a format string.
 #[derive(Debug)]
 pub struct Specifiers<'src> {
@@ -161,6 +661,13 @@ impl<'src> Specifiers<'src> {
         let span = self.lex.span();
         format_offset + span.start..format_offset + span.end
     }
+
+    /// Returns the conversion letter of the last specifier if the text right
+    /// after it repeats it, like the second `d` in `%dd`.
+    pub fn repeated_conversion(&self, specifier: &Specifier<'_>) -> Option<char> {
+        let conversion = specifier.conversion.chars().last()?;
+        self.remainder.starts_with(conversion).then_some(conversion)
+    }
 }
 
 impl<'src> Iterator for Specifiers<'src> {
@@ -180,9 +687,312 @@ impl<'src> Iterator for Specifiers<'src> {
     }
 }
 
+/// Returns the spans of conversions in a format string that aren't supported,
+/// e.g. the `%q` in `"%d and %q"`.
+pub fn unknown_conversions<'a>(
+    format: &'a str,
+    config: &'a Config,
+) -> impl Iterator<Item = Range<usize>> + 'a {
+    FormatToken::lexer(format)
+        .spanned()
+        .filter_map(|(token, span)| match token {
+            FormatToken::Unknown if split_specifier(format, span.clone()).is_some() => None,
+            FormatToken::Unknown => Some(span),
+            FormatToken::Specifier(Specifier {
+                conversion: "C" | "S",
+                ..
+            }) if !config.ms_compat => Some(span),
+            _ => None,
+        })
+}
+
+/// Returns the spans of specifiers that the C standard being validated
+/// against doesn't have, e.g. the `%b` in `"%b"` before C23.
+pub fn unsupported_specifiers<'a>(
+    format: &'a str,
+    config: &'a Config,
+) -> impl Iterator<Item = Range<usize>> + 'a {
+    FormatToken::lexer(format)
+        .spanned()
+        .filter_map(|(token, span)| match token {
+            FormatToken::Specifier(specifier)
+                if matches!(specifier.conversion_char(), 'b' | 'B')
+                    && config.standard < Standard::C23 =>
+            {
+                Some(span)
+            }
+            _ => None,
+        })
+}
+
+/// Returns the spans of specifiers that are split across adjacent string
+/// literals, e.g. the `%5` in `"%5" "d"`, which can't be lexed as one.
+pub fn split_specifiers(format: &str) -> impl Iterator<Item = Range<usize>> + '_ {
+    FormatToken::lexer(format)
+        .spanned()
+        .filter_map(|(token, span)| match token {
+            FormatToken::Unknown => split_specifier(format, span),
+            _ => None,
+        })
+}
+
+/// Returns the span of a `%` and the options after it, if the string
+/// literal ends right after them.
+fn split_specifier(format: &str, span: Range<usize>) -> Option<Range<usize>> {
+    let is_option = |c: char| c.is_ascii_digit() || "$-+ #.*hlLjzt".contains(c);
+    // the lexer may already include some of the options, e.g. `%.2` in `"%.2" "f"`
+    if !format[span.clone()]
+        .strip_prefix('%')?
+        .chars()
+        .all(is_option)
+    {
+        return None;
+    }
+
+    let rest = &format[span.start + 1..];
+    let options = rest.trim_start_matches(is_option);
+    let end = span.start + 1 + rest.len() - options.len();
+    options.starts_with('"').then_some(span.start..end)
+}
+
+/// Returns the spans of the first specifier that references its argument by
+/// position, like `%2$d`, and the first that takes an argument in order,
+/// like `%d` or the `*` in `%1$*d`, if a format string has both.
+pub fn mixed_positions(format: &str) -> Option<(Range<usize>, Range<usize>)> {
+    let mut positional = None;
+    let mut sequential = None;
+    for (token, span) in FormatToken::lexer(format).spanned() {
+        if let FormatToken::Specifier(specifier) = token {
+            let stars: Vec<_> = specifier.star_positions().collect();
+            if specifier.position.is_some() || stars.iter().any(|(_, p)| p.is_some()) {
+                positional.get_or_insert(span.clone());
+            }
+            if specifier.position.is_none() || stars.iter().any(|(_, p)| p.is_none()) {
+                sequential.get_or_insert(span);
+            }
+        }
+    }
+
+    positional.zip(sequential)
+}
+
+/// Returns the spans of escape sequences in a format string that aren't
+/// valid C, like `\q` or `\400`, each with the reason why.
+pub fn invalid_escapes(format: &str) -> impl Iterator<Item = (Range<usize>, &'static str)> + '_ {
+    format.match_indices('\\').filter_map(move |(start, _)| {
+        // skip backslashes that are escaped themselves, like the second one in `\\`
+        let preceding = format[..start]
+            .bytes()
+            .rev()
+            .take_while(|&b| b == b'\\')
+            .count();
+        if preceding % 2 == 1 {
+            return None;
+        }
+
+        let rest = &format[start + 1..];
+        let digits = |radix: u32, max: usize| {
+            rest[1..]
+                .chars()
+                .take(max)
+                .take_while(|c| c.is_digit(radix))
+                .count()
+        };
+        let (len, reason) = match rest.chars().next()? {
+            '\'' | '"' | '%' | '?' | '\\' | 'a' | 'b' | 'e' | 'f' | 'n' | 'r' | 't' | 'v'
+            | '\r' | '\n' => return None,
+            '0'..='7' => {
+                let len = 1 + digits(8, 2);
+                match u32::from_str_radix(&rest[..len], 8) {
+                    Ok(value) if value > 0o377 => (len, "octal escape doesn't fit in a byte"),
+                    _ => return None,
+                }
+            }
+            'x' => match digits(16, usize::MAX) {
+                0 => (1, "`\\x` needs hexadecimal digits"),
+                len if u32::from_str_radix(&rest[1..1 + len], 16).map_or(true, |v| v > 0xff) => {
+                    (1 + len, "hexadecimal escape doesn't fit in a byte")
+                }
+                _ => return None,
+            },
+            'u' if digits(16, 4) == 4 => return None,
+            'U' if digits(16, 8) == 8 => return None,
+            'u' => (1 + digits(16, 4), "`\\u` needs 4 hexadecimal digits"),
+            'U' => (1 + digits(16, 8), "`\\U` needs 8 hexadecimal digits"),
+            c => (c.len_utf8(), "not an escape sequence"),
+        };
+        Some((start..start + 1 + len, reason))
+    })
+}
+
+/// Returns the spans of `%n` specifiers in a format string, which write to
+/// memory instead of formatting an argument.
+pub fn write_backs(format: &str) -> impl Iterator<Item = Range<usize>> + '_ {
+    FormatToken::lexer(format)
+        .spanned()
+        .filter_map(|(token, span)| matches!(token, FormatToken::WriteBack).then_some(span))
+}
+
 fn union(span: Option<Range<usize>>, other: Range<usize>) -> Range<usize> {
     match span {
         Some(span) => span.start..other.end,
         None => other,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the specifiers in a format string.
+    fn specifiers(format: &str) -> Vec<Specifier<'_>> {
+        Specifiers::new(format).collect()
+    }
+
+    #[test]
+    fn color_escapes_before_specifiers() {
+        for (format, before, remainder) in [
+            (r"\033[1;31m%d\033[0m", r"\033[1;31m", r"\033[0m"),
+            (r"\x1b[31m%d\x1B[0m", r"\x1b[31m", r"\x1B[0m"),
+            (r"\33[2J%d\e[0m", r"\33[2J", r"\e[0m"),
+        ] {
+            let mut specifiers = Specifiers::new(format);
+            let specifier = specifiers.next().expect("one specifier");
+            assert_eq!(specifier.ctype, CType::Int, "{format}");
+            assert_eq!(specifiers.before, before);
+            assert_eq!(specifiers.remainder, remainder);
+            assert!(specifiers.next().is_none(), "{format}");
+        }
+    }
+
+    #[test]
+    fn int_literal_suffixes() {
+        for (literal, ctype) in [
+            ("5", CType::Int),
+            ("5u", CType::Unsigned),
+            ("5L", CType::Long),
+            ("5UL", CType::UnsignedLong),
+            ("5lu", CType::UnsignedLong),
+            ("5LL", CType::LongLong),
+            ("5ull", CType::UnsignedLongLong),
+            ("5z", CType::Size),
+            ("0xFFUL", CType::UnsignedLong),
+        ] {
+            assert_eq!(int_literal_ctype(literal), ctype, "{literal}");
+        }
+    }
+
+    #[test]
+    fn sizeof_is_size() {
+        assert!(is_sizeof("sizeof(int)"));
+        assert!(is_sizeof("sizeof (struct point)"));
+        assert!(is_sizeof("sizeof x"));
+        assert!(!is_sizeof("sizeof(x) - 1"));
+        assert!(!is_sizeof("sizeof_x"));
+        assert!(!is_sizeof("sizeof"));
+    }
+
+    #[test]
+    fn unsigned_length_modifiers() {
+        let ctypes: Vec<CType> = specifiers("%lu %llu %ld %lld %hu")
+            .iter()
+            .map(|specifier| specifier.ctype)
+            .collect();
+        assert_eq!(
+            ctypes,
+            [
+                CType::UnsignedLong,
+                CType::UnsignedLongLong,
+                CType::Long,
+                CType::LongLong,
+                CType::Short,
+            ]
+        );
+    }
+
+    #[test]
+    fn format_fn_ignores_length_modifier() {
+        let format_fns: Vec<&str> = specifiers("%x %lx %llX %hho %lB %hhd %hu %lu")
+            .iter()
+            .map(Specifier::format_fn)
+            .collect();
+        assert_eq!(
+            format_fns,
+            [
+                "fmt_hex",
+                "fmt_hex",
+                "fmt_hex_upper",
+                "fmt_octal",
+                "fmt_binary_upper",
+                "fmt_int",
+                "fmt_uint",
+                "fmt_ulong",
+            ]
+        );
+    }
+
+    #[test]
+    fn positional_star_specifier() {
+        let [specifier] = &specifiers("%1$-*2$.*3$d")[..] else {
+            panic!("expected one specifier");
+        };
+        assert_eq!(specifier.position, Some(1));
+        assert_eq!(specifier.options, "-*2$.*3$");
+        assert_eq!(
+            specifier.star_positions().collect::<Vec<_>>(),
+            [(1, Some(2)), (5, Some(3))]
+        );
+    }
+
+    #[test]
+    fn star_without_position() {
+        let [specifier] = &specifiers("%*2$d")[..] else {
+            panic!("expected one specifier");
+        };
+        assert_eq!(specifier.position, None);
+        assert_eq!(specifier.options, "*2$");
+        assert_eq!(
+            specifier.star_positions().collect::<Vec<_>>(),
+            [(0, Some(2))]
+        );
+
+        let [specifier] = &specifiers("%*.*f")[..] else {
+            panic!("expected one specifier");
+        };
+        assert_eq!(
+            specifier.star_positions().collect::<Vec<_>>(),
+            [(0, None), (2, None)]
+        );
+    }
+
+    #[test]
+    fn mixed_positional_stars() {
+        assert_eq!(mixed_positions("%1$*2$d %2$d"), None);
+        assert_eq!(mixed_positions("%*2$d"), Some((0..5, 0..5)));
+        assert_eq!(mixed_positions("%1$*d"), Some((0..5, 0..5)));
+        assert_eq!(mixed_positions("%1$d %*d"), Some((0..4, 5..8)));
+    }
+
+    #[test]
+    fn invalid_escape_sequences() {
+        let invalid = |format| invalid_escapes(format).collect::<Vec<_>>();
+        assert!(invalid(r#"\n\t\\\'\"\?\123\0\xFF\xffé\U0001F600"#).is_empty());
+        assert!(invalid("line \\\ncontinued").is_empty());
+        assert_eq!(invalid(r"\q"), [(0..2, "not an escape sequence")]);
+        // the `q` after an escaped backslash isn't escaped
+        assert!(invalid(r"\\q").is_empty());
+        assert_eq!(
+            invalid(r"a\400"),
+            [(1..5, "octal escape doesn't fit in a byte")]
+        );
+        assert_eq!(invalid(r"\x"), [(0..2, "`\\x` needs hexadecimal digits")]);
+        assert_eq!(
+            invalid(r"\x100"),
+            [(0..5, "hexadecimal escape doesn't fit in a byte")]
+        );
+        assert_eq!(
+            invalid(r"\u12"),
+            [(0..4, "`\\u` needs 4 hexadecimal digits")]
+        );
+    }
+}
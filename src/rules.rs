@@ -0,0 +1,207 @@
+use crate::error::{Error, Reported};
+use miette::{Diagnostic, Severity};
+use std::collections::HashMap;
+
+/// Levels that a rule's diagnostics can be reported at, overriding the
+/// severity they have by default.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Level {
+    /// Don't report the rule's diagnostics.
+    Allow,
+    /// Report the rule's diagnostics as warnings.
+    Warn,
+    /// Report the rule's diagnostics as errors.
+    Deny,
+}
+
+/// Rule names mapped to the [`Level`] they're reported at, e.g. denying
+/// `excess-args`, which is only a warning by default.
+///
+/// Rules that aren't set keep the severity of their [`Error`] variant.
+#[derive(Debug, Default)]
+pub struct RuleConfig {
+    levels: HashMap<&'static str, Level>,
+    /// Rules from [`Lint`](crate::lint::Lint)s, besides the built-in [`Error::RULES`].
+    lint_rules: Vec<&'static str>,
+    /// Whether warnings are reported as errors.
+    deny_warnings: bool,
+}
+
+impl RuleConfig {
+    /// Registers a rule that a [`Lint`](crate::lint::Lint) reports errors
+    /// under, so that its level can be set.
+    pub fn register(&mut self, rule: &'static str) {
+        if !self.rules().any(|name| name == rule) {
+            self.lint_rules.push(rule);
+        }
+    }
+
+    /// Returns the names of the built-in rules, then the registered ones.
+    pub fn rules(&self) -> impl Iterator<Item = &'static str> + '_ {
+        Error::RULES.iter().chain(&self.lint_rules).copied()
+    }
+
+    /// Sets the level of a rule, or returns an error if there's no rule with that name.
+    pub fn set(&mut self, rule: &str, level: Level) -> Result<(), String> {
+        let rule = self.rules().find(|&name| name == rule).ok_or_else(|| {
+            format!(
+                "unknown rule `{rule}`, expected one of {}",
+                self.rules().collect::<Vec<_>>().join(", ")
+            )
+        })?;
+
+        self.levels.insert(rule, level);
+        Ok(())
+    }
+
+    /// Reports every warning as an error, whether it's a warning by default
+    /// or its rule is set to [`Level::Warn`].
+    pub fn deny_warnings(&mut self) {
+        self.deny_warnings = true;
+    }
+
+    /// Returns the level a rule is set to, if it's been set.
+    pub fn level(&self, rule: &str) -> Option<Level> {
+        self.levels.get(rule).copied()
+    }
+
+    /// Returns the severity an error is reported at, or `None` if its rule is allowed.
+    pub fn severity(&self, error: &Error) -> Option<Severity> {
+        let severity = match self.level(error.rule()) {
+            Some(Level::Allow) => return None,
+            Some(Level::Warn) => Severity::Warning,
+            Some(Level::Deny) => Severity::Error,
+            None => error.severity().unwrap_or(Severity::Error),
+        };
+        match severity {
+            Severity::Warning if self.deny_warnings => Some(Severity::Error),
+            severity => Some(severity),
+        }
+    }
+
+    /// Returns `true` if any of the errors are reported with error severity,
+    /// making the source code invalid.
+    pub fn any_fatal(&self, errors: &[Error]) -> bool {
+        errors
+            .iter()
+            .any(|error| self.severity(error) == Some(Severity::Error))
+    }
+
+    /// Tags each error with its effective severity, dropping errors whose rule is allowed.
+    pub fn apply(&self, errors: Vec<Error>) -> Vec<Reported> {
+        errors
+            .into_iter()
+            .filter_map(|error| {
+                let severity = self.severity(&error)?;
+                Some(Reported::new(error, severity))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::ir::IntermediateRepresentation;
+
+    fn rules(levels: &[(&str, Level)]) -> Config {
+        let mut rules = RuleConfig::default();
+        for &(rule, level) in levels {
+            rules.set(rule, level).unwrap();
+        }
+        Config {
+            rules,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn warn_downgrades_excess_specifiers() {
+        let source = r#"printf("%d %d", x);"#;
+        assert!(IntermediateRepresentation::parse(source, &Config::default()).is_err());
+
+        let ir = IntermediateRepresentation::parse(
+            source,
+            &rules(&[("excess-specifiers", Level::Warn)]),
+        )
+        .expect("excess specifiers are only a warning");
+        // the call can't be rewritten, so it's left as is
+        assert_eq!(ir.display_typecast().to_string(), source);
+        let warnings = ir.into_warnings();
+        let [warning] = &warnings[..] else {
+            panic!("expected one warning");
+        };
+        assert_eq!(warning.error().rule(), "excess-specifiers");
+        assert!(!warning.is_error());
+    }
+
+    #[test]
+    fn excess_args_are_warnings() {
+        let source = r#"printf("%d", x, y);"#;
+        let ir = IntermediateRepresentation::parse(source, &Config::default())
+            .expect("excess args are only a warning");
+        // the extra argument is passed through as is
+        assert_eq!(
+            ir.display_typecast().to_string(),
+            r#"printf("%d", (int) (x), y);"#
+        );
+        let warnings = ir.into_warnings();
+        let [warning] = &warnings[..] else {
+            panic!("expected one warning");
+        };
+        assert_eq!(warning.error().rule(), "excess-args");
+        assert!(!warning.is_error());
+
+        let errors =
+            IntermediateRepresentation::parse(source, &rules(&[("excess-args", Level::Deny)]))
+                .expect_err("excess args are denied");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].is_error());
+    }
+
+    #[test]
+    fn deny_warnings_makes_them_fatal() {
+        let source = r#"printf("%d", x, y);"#;
+        let mut config = rules(&[]);
+        config.rules.deny_warnings();
+        let errors =
+            IntermediateRepresentation::parse(source, &config).expect_err("warnings are denied");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].is_error());
+
+        // rules set to warn are denied too, but allowed ones stay allowed
+        let mut config = rules(&[
+            ("excess-specifiers", Level::Warn),
+            ("excess-args", Level::Allow),
+        ]);
+        config.rules.deny_warnings();
+        assert!(IntermediateRepresentation::parse(r#"printf("%d %d", x);"#, &config).is_err());
+        assert!(IntermediateRepresentation::parse(source, &config).is_ok());
+    }
+
+    #[test]
+    fn deny_and_allow() {
+        let source = r#"snprintf(buf, 2, "x = %d", x);"#;
+        let errors =
+            IntermediateRepresentation::parse(source, &rules(&[("buffer-too-small", Level::Deny)]))
+                .expect_err("buffer too small is denied");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].is_error());
+
+        let ir = IntermediateRepresentation::parse(
+            source,
+            &rules(&[("buffer-too-small", Level::Allow)]),
+        )
+        .expect("source code is valid");
+        assert!(ir.into_warnings().is_empty());
+    }
+
+    #[test]
+    fn unknown_rule() {
+        let error = RuleConfig::default()
+            .set("no-such-rule", Level::Warn)
+            .unwrap_err();
+        assert!(error.starts_with("unknown rule `no-such-rule`"), "{error}");
+    }
+}
@@ -0,0 +1,421 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{self, Command, Output, Stdio};
+use std::{env, fs};
+
+/// Returns an empty directory for a test to write files to.
+fn scratch_dir(test: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("safe_printf-cli-{test}-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn compile_commands_validates_each_file() {
+    let dir = scratch_dir("compile-commands");
+    fs::create_dir(dir.join("src")).unwrap();
+    fs::write(dir.join("src/main.c"), "printf(\"%d\\n\", x);\n").unwrap();
+    fs::write(dir.join("src/util.c"), "printf(\"%s\\n\");\n").unwrap();
+    let json = format!(
+        r#"[
+            {{ "directory": "{dir}", "file": "src/main.c", "command": "cc -c src/main.c" }},
+            {{ "directory": "{dir}", "file": "src/util.c", "command": "cc -c src/util.c" }}
+        ]"#,
+        dir = dir.display()
+    );
+    let path = dir.join("compile_commands.json");
+    fs::write(&path, json).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_safe_printf"))
+        .arg("--compile-commands")
+        .arg(&path)
+        .output()
+        .expect("binary runs");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(output.status.code(), Some(1), "{stderr}");
+    assert!(stderr.contains("util.c"), "{stderr}");
+    assert!(!stderr.contains("main.c"), "{stderr}");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn only_errors_hides_warnings() {
+    let dir = scratch_dir("only-errors");
+    let path = dir.join("main.c");
+    let run = |only_errors: bool| {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_safe_printf"));
+        if only_errors {
+            command.arg("--only-errors");
+        }
+        let output = command.arg(&path).output().expect("binary runs");
+        (
+            output.status.code(),
+            String::from_utf8(output.stderr).unwrap(),
+        )
+    };
+
+    fs::write(&path, "snprintf(buf, 2, \"x = %d\", x);\nprintf(\"%d\");\n").unwrap();
+    let (code, stderr) = run(true);
+    assert_eq!(code, Some(1), "{stderr}");
+    assert!(stderr.contains("Excess specifiers"), "{stderr}");
+    assert!(!stderr.contains("Buffer is too small"), "{stderr}");
+
+    fs::write(&path, "snprintf(buf, 2, \"x = %d\", x);\n").unwrap();
+    let (code, stderr) = run(false);
+    assert_eq!(code, Some(0), "{stderr}");
+    assert!(stderr.contains("Buffer is too small"), "{stderr}");
+    assert_eq!(run(true), (Some(0), String::new()));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn hidden_warnings_still_fail() {
+    let dir = scratch_dir("error-on");
+    fs::write(dir.join("main.c"), "snprintf(buf, 2, \"x = %d\", x);\n").unwrap();
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_safe_printf"))
+            .arg("main.c")
+            .args(args)
+            .current_dir(&dir)
+            .output()
+            .expect("binary runs")
+    };
+
+    // the exit status counts diagnostics before they're hidden
+    let output = run(&["--error-on", "warning", "--min-severity", "error"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(output.stderr.is_empty());
+
+    let output = run(&["--format", "short", "--error-on", "warning"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("warning: Buffer is too small"), "{stdout}");
+
+    assert_eq!(run(&["--min-severity", "error"]).status.code(), Some(0));
+    assert_eq!(
+        run(&["--only-errors", "--min-severity", "warning"])
+            .status
+            .code(),
+        Some(2)
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn junit_reports_each_error() {
+    let dir = scratch_dir("junit");
+    let path = dir.join("main.c");
+    fs::write(&path, "printf(\"%d\");\nprintf(\"%s\", 5);\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_safe_printf"))
+        .args(["--format", "junit"])
+        .arg(&path)
+        .output()
+        .expect("binary runs");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(output.status.code(), Some(1), "{stdout}");
+    assert!(stdout.starts_with("<?xml"), "{stdout}");
+    assert!(
+        stdout.contains("<testsuite name=\"safe_printf\" tests=\"1\" failures=\"2\">"),
+        "{stdout}"
+    );
+    assert_eq!(stdout.matches("<failure ").count(), 2, "{stdout}");
+    assert!(stdout.trim_end().ends_with("</testsuite>"), "{stdout}");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn optimize_refuses_star_widths() {
+    let dir = scratch_dir("optimize-star");
+    let path = dir.join("main.c");
+    let optimized = dir.join("optimized.c");
+    fs::write(&path, "printf(\"%*d\\n\", width, x);\n").unwrap();
+    let run = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_safe_printf"))
+            .args(extra)
+            .arg("--optimize")
+            .arg(&optimized)
+            .arg(&path)
+            .output()
+            .expect("binary runs")
+    };
+
+    let output = run(&[]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(output.status.code(), Some(1), "{stderr}");
+    assert!(stderr.contains("can't be optimized"), "{stderr}");
+    assert!(!optimized.exists());
+
+    let output = run(&["--allow", "unoptimizable-star"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(optimized.exists());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+/// Runs the binary with `input` piped to its stdin.
+fn run_with_stdin(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_safe_printf"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("binary runs");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn dash_reads_from_stdin() {
+    let output = run_with_stdin(&["-"], "printf(\"%d\\n\");\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(output.status.code(), Some(1), "{stderr}");
+    assert!(stderr.contains("<stdin>"), "{stderr}");
+    assert!(stderr.contains("Excess specifiers"), "{stderr}");
+}
+
+#[test]
+fn dash_writes_to_stdout() {
+    let output = run_with_stdin(&["-", "--typecast", "-"], "printf(\"%d\\n\", x);\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "printf(\"%d\\n\", (int) (x));\n"
+    );
+
+    // empty input has no calls, so it's valid
+    let output = run_with_stdin(&["-", "--typecast", "-"], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn json_reports_each_error() {
+    let dir = scratch_dir("json");
+    let path = dir.join("main.c");
+    let run = || {
+        Command::new(env!("CARGO_BIN_EXE_safe_printf"))
+            .args(["--format", "json"])
+            .arg(&path)
+            .output()
+            .expect("binary runs")
+    };
+
+    fs::write(&path, "printf(\"%d\");\nprintf(\"%s\", 5);\n").unwrap();
+    let output = run();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(output.status.code(), Some(1), "{stdout}");
+    let json: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    let errors = json[0]["errors"].as_array().unwrap();
+    assert_eq!(errors.len(), 2, "{stdout}");
+    assert_eq!(errors[0]["rule"], "excess-specifiers");
+    assert_eq!(errors[1]["rule"], "specifier-arg-mismatch");
+    assert_eq!(
+        (&errors[1]["line"], &errors[1]["column"]),
+        (&2.into(), &9.into())
+    );
+    assert_eq!(errors[1]["labels"][0]["start"], 22);
+
+    fs::write(&path, "printf(\"%d\", x);\n").unwrap();
+    let output = run();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn validates_every_file() {
+    let dir = scratch_dir("several-files");
+    fs::write(dir.join("a.c"), "printf(\"%s\\n\");\n").unwrap();
+    fs::write(dir.join("b.c"), "printf(\"%d\\n\", x);\n").unwrap();
+    fs::write(dir.join("c.c"), "printf(\"%d %d\\n\", x);\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_safe_printf"))
+        .args(["a.c", "b.c", "c.c", "--typecast", "out"])
+        .current_dir(&dir)
+        .output()
+        .expect("binary runs");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(output.status.code(), Some(1), "{stderr}");
+    assert!(stderr.contains("a.c"), "{stderr}");
+    assert!(!stderr.contains("b.c"), "{stderr}");
+    assert!(stderr.contains("c.c"), "{stderr}");
+    assert_eq!(
+        fs::read_to_string(dir.join("out/b.c")).unwrap(),
+        "printf(\"%d\\n\", (int) (x));\n"
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn exit_codes_encode_the_failure() {
+    let dir = scratch_dir("exit-codes");
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_safe_printf"))
+            .args(args)
+            .current_dir(&dir)
+            .output()
+            .expect("binary runs")
+            .status
+            .code()
+    };
+
+    fs::write(dir.join("valid.c"), "printf(\"%d\\n\", x);\n").unwrap();
+    fs::write(dir.join("invalid.c"), "printf(\"%d\\n\");\n").unwrap();
+    assert_eq!(run(&["valid.c"]), Some(0));
+    assert_eq!(run(&["invalid.c"]), Some(1));
+    // the errors are still rendered, not just signaled by the exit code
+    let output = Command::new(env!("CARGO_BIN_EXE_safe_printf"))
+        .arg("invalid.c")
+        .current_dir(&dir)
+        .output()
+        .expect("binary runs");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("invalid.c:1:1"), "{stderr}");
+    assert!(stderr.contains("not enough arguments"), "{stderr}");
+    assert_eq!(run(&["missing.c"]), Some(2));
+    assert_eq!(run(&["valid.c", "--no-such-flag"]), Some(2));
+    // the output already exists, so it can't be written
+    assert_eq!(run(&["valid.c", "--typecast", "invalid.c"]), Some(2));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn warnings_only_fail_when_denied() {
+    let dir = scratch_dir("deny-warnings");
+    fs::write(dir.join("warn.c"), "printf(\"%d\\n\", x, y);\n").unwrap();
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_safe_printf"))
+            .args(args)
+            .current_dir(&dir)
+            .output()
+            .expect("binary runs")
+    };
+
+    let output = run(&["warn.c", "--typecast", "typecast.c"]);
+    assert_eq!(output.status.code(), Some(0));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("Warning:"), "{stderr}");
+    assert!(stderr.contains("Excess arguments."), "{stderr}");
+    assert_eq!(
+        fs::read_to_string(dir.join("typecast.c")).unwrap(),
+        "printf(\"%d\\n\", (int) (x), y);\n"
+    );
+
+    let output = run(&["warn.c", "--deny-warnings"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("Error:"), "{stderr}");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn fix_rewrites_valid_files_in_place() {
+    let dir = scratch_dir("fix");
+    fs::write(dir.join("valid.c"), "printf(\"%d\\n\", x);\n").unwrap();
+    fs::write(dir.join("invalid.c"), "printf(\"%d\\n\");\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_safe_printf"))
+        .args(["valid.c", "invalid.c", "--fix"])
+        .current_dir(&dir)
+        .output()
+        .expect("binary runs");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        fs::read_to_string(dir.join("valid.c")).unwrap(),
+        "printf(\"%d\\n\", (int) (x));\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("invalid.c")).unwrap(),
+        "printf(\"%d\\n\");\n"
+    );
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn check_prints_a_summary() {
+    let dir = scratch_dir("check");
+    fs::write(
+        dir.join("main.c"),
+        "printf(\"%d\\n\", x);\nputs(\"hi\");\nprintf(\"hi\\n\");\n",
+    )
+    .unwrap();
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_safe_printf"))
+            .args(args)
+            .current_dir(&dir)
+            .output()
+            .expect("binary runs")
+    };
+
+    let output = run(&["main.c", "--check"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "OK: 2 printf sites validated in main.c\n"
+    );
+    assert!(output.stderr.is_empty());
+
+    let output = run(&["main.c"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("no output was produced"), "{stderr}");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn summary_counts_human_diagnostics() {
+    let dir = scratch_dir("summary");
+    fs::write(dir.join("main.c"), "printf(\"%d\");\nprintf(\"%s %s\");\n").unwrap();
+    let stderr = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_safe_printf"))
+            .args(args)
+            .current_dir(&dir)
+            .output()
+            .expect("binary runs");
+        String::from_utf8(output.stderr).unwrap()
+    };
+
+    let summary = "2 errors: 2 excess-specifiers";
+    assert!(stderr(&["main.c"]).contains(summary));
+    assert!(!stderr(&["main.c", "--quiet"]).contains(summary));
+    assert!(!stderr(&["main.c", "--format", "json"]).contains(summary));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn brief_prints_a_line_for_each_diagnostic() {
+    let dir = scratch_dir("brief");
+    fs::write(dir.join("main.c"), "// é\ns = \"é\"; printf(\"%d\");\n").unwrap();
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_safe_printf"))
+            .args(args)
+            .current_dir(&dir)
+            .output()
+            .expect("binary runs")
+    };
+
+    let output = run(&["main.c", "--brief"]);
+    assert_eq!(output.status.code(), Some(1));
+    // columns count characters, not bytes
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "main.c:2:17: error: Excess specifiers, this will read arbitrary data off the stack! [excess-specifiers]\n"
+    );
+    assert_eq!(
+        run(&["main.c", "--brief", "--format", "json"])
+            .status
+            .code(),
+        Some(2)
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,35 @@
+use safe_printf::{validate, Config, IntermediateRepresentation};
+
+#[test]
+fn valid_source_code() {
+    let ir =
+        validate("int main() {\n    printf(\"%d\\n\", x);\n}\n").expect("source code is valid");
+    assert_eq!(ir.sites().count(), 1);
+    assert_eq!(
+        ir.display_typecast().to_string(),
+        "int main() {\n    printf(\"%d\\n\", (int) (x));\n}\n"
+    );
+}
+
+#[test]
+fn invalid_source_code() {
+    let errors = validate(r#"printf("%d %s\n", x);"#).expect_err("source code is invalid");
+    let rules: Vec<&str> = errors.iter().map(|error| error.error().rule()).collect();
+    assert_eq!(rules, ["excess-specifiers"]);
+}
+
+#[test]
+fn custom_config() {
+    let source = r#"printf("%lf\n", x);"#;
+    assert!(validate(source)
+        .expect("source code is valid")
+        .into_warnings()
+        .is_empty());
+
+    let config = Config {
+        pedantic: true,
+        ..Config::default()
+    };
+    let ir = IntermediateRepresentation::parse(source, &config).expect("source code is valid");
+    assert_eq!(ir.into_warnings().len(), 1);
+}